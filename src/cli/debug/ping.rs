@@ -8,9 +8,9 @@ pub struct Args {}
 impl Args {
     pub async fn execute(self, config: Config, logger: Logger) -> miette::Result<()> {
         let service = config.service(&logger, false).await?;
-        logger.log("Ping...");
+        logger.info("Ping...");
         service.info().await.d()?;
-        logger.log("...Pong!");
+        logger.info("...Pong!");
         Ok(())
     }
 }
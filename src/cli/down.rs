@@ -1,9 +1,10 @@
 use clap::Parser;
-use podman_api::opts::{ContainerListFilter, ContainerListOpts, NetworkListFilter, NetworkListOpts};
+use podman_api::opts::{ContainerListFilter, ContainerListOpts, NetworkListFilter, NetworkListOpts, PodListFilter, PodListOpts};
 
 use crate::{
     config::Config,
     logger::Logger,
+    plan::schedule,
     utils::{IntoDiagnosticShorthand, XTug},
 };
 
@@ -13,7 +14,14 @@ pub struct Args {}
 impl Args {
     pub async fn execute(self, config: Config, logger: Logger) -> miette::Result<()> {
         let service = config.service(&logger, false).await?;
-        logger.log("Containers");
+        if config.raw_service().uri().starts_with("unix://") {
+            logger.info("Schedules");
+            for (unit, _) in schedule::local_schedule_units(&config.group).await? {
+                logger.info(&unit);
+                schedule::write_units(&unit, None).await.d()?;
+            }
+        }
+        logger.info("Containers");
         let containers = service
             .containers()
             .list(
@@ -30,13 +38,28 @@ impl Args {
         for container in containers {
             let running = container.state.as_deref() == Some("running");
             let container = service.containers().get(container.id.unwrap());
-            logger.log(container.id());
+            logger.info(container.id());
             if running {
                 container.stop(&Default::default()).await.d()?;
             }
             container.delete(&Default::default()).await.d()?;
         }
-        logger.log("Networks");
+        logger.info("Pods");
+        let pods = service
+            .pods()
+            .list(
+                &PodListOpts::builder()
+                    .filter([PodListFilter::LabelKeyVal(XTug::Group.to_string(), config.group.clone())])
+                    .build(),
+            )
+            .await
+            .d()?;
+        for pod in pods {
+            let id = pod.id.unwrap();
+            logger.info(&id);
+            service.pods().get(id).remove().await.d()?;
+        }
+        logger.info("Networks");
         let networks = service
             .networks()
             .list(
@@ -48,10 +71,20 @@ impl Args {
             .d()?;
         for network in networks {
             let id = network.id.unwrap();
-            logger.log(&id);
+            logger.info(&id);
             let network = service.networks().get(id);
             network.delete().await.d()?;
         }
+        logger.info("Secrets");
+        for secret in service.secrets().list().await.d()? {
+            let labels = secret.spec.and_then(|spec| spec.labels).unwrap_or_default();
+            if labels.get(XTug::Group.as_ref()) != Some(&config.group) {
+                continue;
+            }
+            let id = secret.id.unwrap();
+            logger.info(&id);
+            service.secrets().get(id).delete().await.d()?;
+        }
 
         Ok(())
     }
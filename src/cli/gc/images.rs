@@ -0,0 +1,20 @@
+use clap::Parser;
+
+use crate::{config::Config, logger::Logger, plan::image};
+
+#[derive(Parser)]
+pub struct Args {
+    /// How many unused versions of every image to keep for rollbacks
+    #[arg(long, default_value_t = 2)]
+    keep: usize,
+}
+
+impl Args {
+    pub async fn execute(self, config: Config, logger: Logger) -> miette::Result<()> {
+        let service = config.service(&logger, false).await?;
+        image::collect_garbage(&service, &config.group, &logger, self.keep).await?;
+        logger.info("Done!");
+
+        Ok(())
+    }
+}
@@ -0,0 +1,24 @@
+mod images;
+
+use clap::Parser;
+
+use crate::{config::Config, logger::Logger};
+
+#[derive(Parser)]
+pub struct Args {
+    #[clap(subcommand)]
+    subcommand: Subcommand,
+}
+
+#[derive(Parser)]
+pub enum Subcommand {
+    Images(images::Args),
+}
+
+impl Args {
+    pub async fn execute(self, config: Config, logger: Logger) -> miette::Result<()> {
+        match self.subcommand {
+            Subcommand::Images(args) => args.execute(config, logger).await,
+        }
+    }
+}
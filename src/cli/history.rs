@@ -0,0 +1,49 @@
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use clap::Parser;
+
+use crate::{config::Config, logger::Logger};
+
+#[derive(Parser)]
+pub struct Args {}
+
+impl Args {
+    pub async fn execute(self, config: Config, logger: Logger) -> miette::Result<()> {
+        let service = config.service(&logger, true).await?;
+        let revisions = crate::history::list(&service, &config.raw_service(), &config.group).await?;
+        if revisions.is_empty() {
+            logger.info("Nothing synced yet");
+            return Ok(());
+        }
+
+        let now = SystemTime::now().duration_since(UNIX_EPOCH).unwrap_or_default().as_secs();
+        println!("{:<10}{:<16}{:<8}NOTE", "REVISION", "DEPLOYED", "IMAGES");
+        for (index, (_, revision)) in revisions.iter().enumerate().rev() {
+            let mut note = Vec::new();
+            if index == revisions.len() - 1 {
+                note.push("current".to_string());
+            }
+            if let Some(number) = revision.rollback_of {
+                note.push(format!("rollback to {number}"));
+            }
+            println!(
+                "{:<10}{:<16}{:<8}{}",
+                revision.number,
+                ago(now.saturating_sub(revision.time)),
+                revision.images.len(),
+                note.join(", ")
+            );
+        }
+
+        Ok(())
+    }
+}
+
+fn ago(seconds: u64) -> String {
+    match seconds {
+        0..=59 => "just now".to_string(),
+        60..=3599 => format!("{}m ago", seconds / 60),
+        3600..=86399 => format!("{}h ago", seconds / 3600),
+        _ => format!("{}d ago", seconds / 86400),
+    }
+}
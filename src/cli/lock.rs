@@ -0,0 +1,63 @@
+use std::{collections::BTreeMap, path::PathBuf};
+
+use clap::Parser;
+
+use crate::{
+    config::Config,
+    lock::{self, Lock, LockedImage, LOCK_FILE},
+    logger::Logger,
+};
+
+#[derive(Parser)]
+pub struct Args {
+    directory: PathBuf,
+    /// Only re-resolve these images, repeat for more
+    #[arg(long, value_name = "IMAGE")]
+    update: Vec<String>,
+}
+
+impl Args {
+    pub async fn execute(self, config: Config, logger: Logger) -> miette::Result<()> {
+        let document = crate::parse::parse(&logger, &self.directory)?;
+        // local images come from `tug push`, there's no registry to pin them to
+        let images = document
+            .images
+            .into_iter()
+            .filter(|image| !image.local)
+            .map(|image| ((*image.name).clone(), image.reference))
+            .collect::<BTreeMap<_, _>>();
+        for name in &self.update {
+            if !images.contains_key(name) {
+                miette::bail!("no image named `{name}` to update");
+            }
+        }
+
+        let previous = lock::read(&self.directory)?.unwrap_or_default();
+        let credentials = config.credentials();
+        let mut lock = Lock::default();
+        for (name, reference) in images {
+            let refresh = self.update.is_empty() || self.update.contains(&name);
+            let kept = previous
+                .images
+                .get(&name)
+                .filter(|locked| !refresh && locked.reference == *reference);
+            let locked = match kept {
+                Some(locked) => locked.clone(),
+                None => {
+                    let digest = lock::resolve_digest(&credentials, &reference).await?;
+                    logger.info(format!("Locked {name} to {digest}"));
+                    LockedImage {
+                        reference: (*reference).clone(),
+                        digest,
+                    }
+                }
+            };
+            lock.images.insert(name, locked);
+        }
+
+        lock::write(&self.directory, &lock)?;
+        logger.info(format!("Wrote {}", self.directory.join(LOCK_FILE).display()));
+
+        Ok(())
+    }
+}
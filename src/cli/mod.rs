@@ -1,15 +1,41 @@
 mod debug;
 mod down;
+mod gc;
+mod history;
+mod lock;
+mod pull;
 mod push;
 mod query;
+mod rollback;
+mod secret;
 mod sync;
 
-use clap::Parser;
+use std::{fs::File, path::PathBuf};
 
-use crate::{config::Config, logger::Logger};
+use clap::{ArgAction, Parser};
+use miette::Context;
+
+use crate::{
+    config::Config,
+    logger::{Level, LogFormat, Logger},
+    plan::Interrupts,
+    utils::IntoDiagnosticShorthand,
+};
 
 #[derive(Parser)]
 pub struct Args {
+    /// Log more, repeat for even more
+    #[arg(short, long, action = ArgAction::Count, global = true)]
+    verbose: u8,
+    /// Log less, repeat for even less
+    #[arg(short, long, action = ArgAction::Count, global = true, conflicts_with = "verbose")]
+    quiet: u8,
+    /// How to format log lines
+    #[arg(long, value_enum, default_value_t = LogFormat::Text, global = true)]
+    log_format: LogFormat,
+    /// Also append log lines to this file
+    #[arg(long, global = true)]
+    log_file: Option<PathBuf>,
     #[clap(subcommand)]
     subcommand: Subcommand,
 }
@@ -18,19 +44,51 @@ pub struct Args {
 pub enum Subcommand {
     Debug(debug::Args),
     Down(down::Args),
+    Gc(gc::Args),
+    History(history::Args),
+    Lock(lock::Args),
+    Pull(pull::Args),
     Push(push::Args),
     Query(query::Args),
+    Rollback(rollback::Args),
+    Secret(secret::Args),
     Sync(sync::Args),
 }
 
 impl Args {
-    pub async fn execute(self, config: Config, logger: Logger) -> miette::Result<()> {
+    pub fn logger(&self) -> miette::Result<Logger> {
+        let file = match &self.log_file {
+            Some(path) => Some(
+                File::options()
+                    .create(true)
+                    .append(true)
+                    .open(path)
+                    .d()
+                    .wrap_err_with(|| format!("opening log file {}", path.display()))?,
+            ),
+            None => None,
+        };
+
+        Ok(Logger::new(
+            Level::from_verbosity(self.verbose, self.quiet),
+            self.log_format,
+            file,
+        ))
+    }
+
+    pub async fn execute(self, config: Config, logger: Logger, interrupts: &Interrupts) -> miette::Result<()> {
         match self.subcommand {
             Subcommand::Debug(args) => args.execute(config, logger).await,
             Subcommand::Down(args) => args.execute(config, logger).await,
-            Subcommand::Push(args) => args.execute(config, logger).await,
+            Subcommand::Gc(args) => args.execute(config, logger).await,
+            Subcommand::History(args) => args.execute(config, logger).await,
+            Subcommand::Lock(args) => args.execute(config, logger).await,
+            Subcommand::Pull(args) => args.execute(config, logger).await,
+            Subcommand::Push(args) => args.execute(config, logger, interrupts).await,
             Subcommand::Query(args) => args.execute(config, logger).await,
-            Subcommand::Sync(args) => args.execute(config, logger).await,
+            Subcommand::Rollback(args) => args.execute(config, logger, interrupts).await,
+            Subcommand::Secret(args) => args.execute(config, logger).await,
+            Subcommand::Sync(args) => args.execute(config, logger, interrupts).await,
         }
     }
 }
@@ -0,0 +1,43 @@
+use std::path::PathBuf;
+
+use clap::Parser;
+
+use crate::{config::Config, logger::Logger, plan::Executor};
+
+#[derive(Parser)]
+pub struct Args {
+    directory: PathBuf,
+    /// How many images to pull at once [default: 5]
+    #[arg(long, value_parser = clap::value_parser!(u16).range(1..))]
+    parallelism: Option<u16>,
+}
+
+impl Args {
+    pub async fn execute(self, config: Config, logger: Logger) -> miette::Result<()> {
+        logger.enable_progress();
+
+        // going through a plan picks up the locked digests and pull policies
+        // exactly like a sync would
+        let document = crate::parse::parse(&logger, &self.directory)?;
+        let lock = crate::lock::read(&self.directory)?;
+        let mut executor = Executor::new();
+        if let Some(parallelism) = self.parallelism {
+            executor.parallelism = parallelism.into();
+        }
+        crate::prepare::prepare(&logger, document, lock.as_ref(), false, &mut executor)?;
+
+        let service = config.service(&logger, false).await?;
+        logger.info("Pulling images");
+        crate::plan::image::pre_pull(
+            &service,
+            &config.credentials(),
+            &logger,
+            executor.image_actions(),
+            executor.parallelism,
+        )
+        .await?;
+        logger.info("Done!");
+
+        Ok(())
+    }
+}
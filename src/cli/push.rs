@@ -1,107 +1,361 @@
-use std::collections::BTreeSet;
+use std::{
+    collections::{BTreeSet, HashSet},
+    io,
+    path::{Path, PathBuf},
+};
 
+use async_compression::futures::write::GzipEncoder;
 use clap::Parser;
-use futures_util::TryStreamExt;
+use futures_util::{AsyncWriteExt, StreamExt, TryStreamExt};
+use hyper::Method;
+use indicatif::HumanBytes;
+use miette::Context;
 use podman_api::{
+    models::LibpodImageSummary,
     opts::{ImageExportOpts, ImageListFilter, ImageListOpts, ImageTagOpts},
     Podman,
 };
+use tokio::sync::OnceCell;
 
-use crate::{config::Config, logger::Logger, utils::IntoDiagnosticShorthand};
+use crate::{
+    config::Config,
+    logger::{Logger, Progress},
+    plan::{image::tracking_repository, Interrupts, Retry},
+    utils::{BodyWriter, IntoDiagnosticShorthand, RawService},
+};
 
 #[derive(Parser)]
 pub struct Args {
+    /// Push every image marked `local=true` in these configs
+    directory: Option<PathBuf>,
+    /// Push the images matching this reference instead
+    #[arg(short, long, conflicts_with = "directory", required_unless_present = "directory")]
+    tag: Option<String>,
+    /// The podman socket to push from [default: your rootless one]
     #[arg(short, long)]
-    tag: String,
-    #[arg(short, long)]
-    local: String,
-    #[arg(short, long)]
+    local: Option<String>,
+    /// Pick which of the matching images to push
+    #[arg(short, long, requires = "tag")]
     select: bool,
+    /// Run `tug sync` on the directory once everything's pushed
+    #[arg(long, requires = "directory")]
+    sync: bool,
+    /// How many images to push at once
+    #[arg(long, default_value_t = 2, value_parser = clap::value_parser!(u16).range(1..))]
+    parallelism: u16,
 }
 
 impl Args {
-    pub async fn execute(self, config: Config, logger: Logger) -> miette::Result<()> {
+    pub async fn execute(self, config: Config, logger: Logger, interrupts: &Interrupts) -> miette::Result<()> {
+        logger.enable_progress();
         let remote = config.service(&logger, false).await?;
-        let local = Podman::new(&self.local).d()?;
-
-        let (name, tag) = match self.tag.rsplit_once(':') {
-            Some((name, tag)) => (name, Some(tag.to_string())),
-            None => (self.tag.as_str(), None),
+        let local = match &self.local {
+            Some(local) => Podman::new(local).d()?,
+            None => match dirs::runtime_dir() {
+                Some(runtime_dir) => Podman::unix(runtime_dir.join("podman/podman.sock")),
+                None => miette::bail!("can't find your podman socket, pass it with --local"),
+            },
         };
 
-        let mut images = local
-            .images()
-            .list(
-                &ImageListOpts::builder()
-                    .filter([ImageListFilter::Reference(name.into(), tag)])
-                    .build(),
-            )
-            .await
-            .d()?;
-
-        if self.select {
-            match dialoguer::MultiSelect::new()
-                .items(
-                    &images
-                        .iter()
-                        .map(|image| {
-                            let names = image.names.clone().unwrap_or_default().join(", ");
-                            let mut id = image.id.clone().unwrap_or_default();
-                            id.truncate(12);
-                            format!("{names} ({id})")
-                        })
-                        .collect::<Vec<_>>(),
-                )
-                .interact_opt()
-                .d()?
-            {
-                Some(selections) => {
-                    let selections = BTreeSet::from_iter(selections);
-                    images = images
-                        .into_iter()
-                        .enumerate()
-                        .filter(|(index, _)| selections.contains(index))
-                        .map(|(_, image)| image)
-                        .collect::<Vec<_>>();
-                }
-                None => {
-                    logger.log("Cancelled");
-                    return Ok(());
+        let images = match (&self.directory, &self.tag) {
+            (Some(directory), _) => config_images(&logger, &local, directory).await?,
+            (None, Some(tag)) => {
+                let images = find_images(&local, tag).await?;
+                if self.select {
+                    match select(images)? {
+                        Some(images) => images,
+                        None => {
+                            logger.info("Cancelled");
+                            return Ok(());
+                        }
+                    }
+                } else {
+                    images
                 }
             }
+            (None, None) => unreachable!("clap requires one of them"),
+        };
+
+        let parallelism = self.parallelism.into();
+        let summary = logger.counter(images.len() as u64, "images");
+        let raw_remote = config.raw_service();
+        let remote_layers = RemoteLayers {
+            remote: &remote,
+            group: &config.group,
+            repositories: images
+                .iter()
+                .flat_map(|image| image.repo_tags.iter().flatten())
+                .map(|repo_tag| {
+                    repo_tag
+                        .rsplit_once(':')
+                        .map_or(repo_tag.as_str(), |(repo, _)| repo)
+                        .to_string()
+                })
+                .collect(),
+            layers: OnceCell::new(),
+        };
+        futures_util::stream::iter(images)
+            .map(|image| {
+                let (local, remote, raw_remote, logger, summary, remote_layers) =
+                    (&local, &remote, &raw_remote, &logger, &summary, &remote_layers);
+                async move {
+                    push_image(local, remote, raw_remote, logger, remote_layers, image).await?;
+                    summary.inc();
+                    miette::Result::<()>::Ok(())
+                }
+            })
+            .buffer_unordered(parallelism)
+            .try_collect::<()>()
+            .await?;
+        summary.finish();
+
+        if let Some(directory) = self.directory.filter(|_| self.sync) {
+            super::sync::Args::new(directory).execute(config, logger, interrupts).await?;
         }
 
-        let local_images = local.images();
+        Ok(())
+    }
+}
 
-        for image in images {
-            let id = image.id.unwrap_or_default();
-            let id_short = &id.as_str()[..12];
-            logger.log(format!("Processing {id_short}"));
+async fn find_images(local: &Podman, reference: &str) -> miette::Result<Vec<LibpodImageSummary>> {
+    let (name, tag) = match reference.rsplit_once(':') {
+        Some((name, tag)) => (name, Some(tag.to_string())),
+        None => (reference, None),
+    };
+
+    local
+        .images()
+        .list(
+            &ImageListOpts::builder()
+                .filter([ImageListFilter::Reference(name.into(), tag)])
+                .build(),
+        )
+        .await
+        .d()
+}
+
+fn select(images: Vec<LibpodImageSummary>) -> miette::Result<Option<Vec<LibpodImageSummary>>> {
+    let selections = dialoguer::MultiSelect::new()
+        .items(
+            &images
+                .iter()
+                .map(|image| {
+                    let names = image.names.clone().unwrap_or_default().join(", ");
+                    let mut id = image.id.clone().unwrap_or_default();
+                    id.truncate(12);
+                    format!("{names} ({id})")
+                })
+                .collect::<Vec<_>>(),
+        )
+        .interact_opt()
+        .d()?;
+
+    Ok(selections.map(|selections| {
+        let selections = BTreeSet::from_iter(selections);
+        images
+            .into_iter()
+            .enumerate()
+            .filter(|(index, _)| selections.contains(index))
+            .map(|(_, image)| image)
+            .collect()
+    }))
+}
+
+// the images a sync of these configs would expect to already be on the remote
+async fn config_images(logger: &Logger, local: &Podman, directory: &Path) -> miette::Result<Vec<LibpodImageSummary>> {
+    let document = crate::parse::parse(logger, directory)?;
+    let mut seen = HashSet::new();
+    let mut images = Vec::new();
+    for image in document.images.iter().filter(|image| image.local) {
+        let found = find_images(local, &image.reference).await?.into_iter().next();
+        let Some(found) = found else {
+            miette::bail!(
+                help = "build it first, or point --local at the podman that has it",
+                "image `{}` ({}) isn't in the local podman",
+                *image.name,
+                *image.reference
+            );
+        };
+        if seen.insert(found.id.clone()) {
+            images.push(found);
+        }
+    }
+
+    Ok(images)
+}
 
-            logger.log("Exporting");
-            let image_object = local_images.get(&id);
-            let mut export_stream = image_object.export(&ImageExportOpts::builder().compress(true).build());
-            let mut buffer = Vec::new();
-            while let Some(chunk) = export_stream.try_next().await.d()? {
-                buffer.extend(chunk);
+async fn push_image(
+    local: &Podman,
+    remote: &Podman,
+    raw_remote: &RawService,
+    logger: &Logger,
+    remote_layers: &RemoteLayers<'_>,
+    image: LibpodImageSummary,
+) -> miette::Result<()> {
+    let id = image.id.unwrap_or_default();
+    let id_short = &id[..12.min(id.len())];
+    let logger = logger.for_resource(id_short);
+    let remote_image = remote.images().get(&id);
+
+    if remote_image.exists().await.d()? {
+        logger.info("Already on the remote");
+    } else {
+        let layers = local
+            .images()
+            .get(&id)
+            .inspect()
+            .await
+            .d()?
+            .root_fs
+            .and_then(|root_fs| root_fs.layers)
+            .unwrap_or_default();
+        let remote_layers = remote_layers.get().await?;
+        let reusable = layers
+            .iter()
+            .filter(|layer| remote_layers.contains(*layer))
+            .cloned()
+            .collect::<HashSet<_>>();
+        logger.debug(format!("Skipping {} of {} layers", reusable.len(), layers.len()));
+
+        let progress = logger.spinner(id_short);
+        let sent = match transfer(local, raw_remote, &id, &reusable, &progress).await {
+            Ok(sent) => sent,
+            Err(err) if !reusable.is_empty() => {
+                logger.warn(format!(
+                    "The remote wouldn't take the image without all of its layers, sending everything ({err})"
+                ));
+                transfer(local, raw_remote, &id, &HashSet::new(), &progress).await?
             }
+            Err(err) => return Err(err),
+        };
+        progress.finish();
+        logger.info(format!("Pushed {}", HumanBytes(sent)));
+    }
 
-            logger.log("Importing");
-            remote.images().load(&buffer).await.d()?;
-            let remote_image = remote.images().get(id);
-            for repo_tag in image.repo_tags.unwrap_or_default() {
-                let (repo, tag) = match repo_tag.rsplit_once(':') {
-                    Some((repo, tag)) => (repo, Some(tag)),
-                    None => (repo_tag.as_str(), None),
-                };
-                let mut opts = ImageTagOpts::builder().repo(repo);
-                if let Some(tag) = tag {
-                    opts = opts.tag(tag);
-                }
-                remote_image.tag(&opts.build()).await.d()?;
+    for repo_tag in image.repo_tags.unwrap_or_default() {
+        let (repo, tag) = match repo_tag.rsplit_once(':') {
+            Some((repo, tag)) => (repo, Some(tag)),
+            None => (repo_tag.as_str(), None),
+        };
+        let mut opts = ImageTagOpts::builder().repo(repo);
+        if let Some(tag) = tag {
+            opts = opts.tag(tag);
+        }
+        remote_image.tag(&opts.build()).await.d()?;
+    }
+
+    Ok(())
+}
+
+// pipes the export straight into the load request, images can be bigger than
+// the memory we've got. podman only exports uncompressed when we have to look
+// inside, so the archive gets gzipped again on the way out.
+//
+// layers in `skip` go over empty. containers/image (which podman's load is
+// built on) asks storage to reuse a layer by its uncompressed digest before it
+// reads that layer from the archive, which podman 4.x does. a version that
+// reads an empty layer anyway fails its digest check instead of storing it, and
+// push_image falls back to sending the whole image
+async fn transfer(
+    local: &Podman,
+    raw_remote: &RawService,
+    id: &str,
+    skip: &HashSet<String>,
+    progress: &Progress,
+) -> miette::Result<u64> {
+    let (writer, body) = BodyWriter::new();
+    let load = tokio::spawn({
+        let raw_remote = raw_remote.clone();
+        async move { raw_remote.request(Method::POST, "/libpod/images/load", body).await }
+    });
+
+    let image_object = local.images().get(id);
+    let export_stream = image_object
+        .export(&ImageExportOpts::builder().build())
+        .map_err(|err| io::Error::new(io::ErrorKind::Other, err))
+        .into_async_read();
+    let mut archive = async_tar::Builder::new(GzipEncoder::new(writer));
+    let mut sent = 0;
+    let pumped = async {
+        let mut entries = async_tar::Archive::new(export_stream).entries().d()?;
+        while let Some(entry) = entries.try_next().await.d()? {
+            let mut header = entry.header().clone();
+            // docker archives name layer files after their uncompressed digest
+            let path = entry.path().d()?.to_string_lossy().into_owned();
+            let skipped = path
+                .strip_suffix(".tar")
+                .map_or(false, |hex| skip.contains(&format!("sha256:{hex}")));
+            if skipped {
+                header.set_size(0);
+                header.set_cksum();
+                archive.append(&header, futures_util::io::empty()).await.d()?;
+            } else {
+                sent += header.size().d()?;
+                archive.append(&header, entry).await.d()?;
             }
+            progress.set_message(format!("{} sent", HumanBytes(sent)));
         }
+        miette::Result::<()>::Ok(())
+    }
+    .await;
 
-        Ok(())
+    if pumped.is_err() {
+        // a half-written archive shouldn't look finished to the remote
+        archive.get_mut().get_mut().abort();
+    } else if let Ok(mut encoder) = archive.into_inner().await {
+        // writes out the gzip trailer, the body ends when it's dropped
+        let _ = encoder.close().await;
+    }
+    // when the remote gives up halfway, its reason beats a broken pipe
+    let loaded = load.await.d()?;
+    pumped?;
+    loaded.wrap_err("importing")?;
+
+    Ok(sent)
+}
+
+// layers the remote already has, by uncompressed digest. only older versions of
+// the images being pushed and the ones the group's syncs track get looked at,
+// which is where shared layers are going to be, and only once an image
+// actually needs sending
+struct RemoteLayers<'a> {
+    remote: &'a Podman,
+    group: &'a str,
+    repositories: HashSet<String>,
+    layers: OnceCell<HashSet<String>>,
+}
+
+impl RemoteLayers<'_> {
+    async fn get(&self) -> miette::Result<&HashSet<String>> {
+        self.layers.get_or_try_init(|| self.list()).await
+    }
+
+    async fn list(&self) -> miette::Result<HashSet<String>> {
+        let images = &self.remote.images();
+        let opts = ImageListOpts::builder().build();
+        let tracked = tracking_repository(self.group, "");
+        let summaries = Retry::QUERY
+            .run(|| images.list(&opts))
+            .await
+            .d()?
+            .into_iter()
+            .filter(|summary| {
+                summary.repo_tags.iter().flatten().any(|repo_tag| {
+                    repo_tag.starts_with(&tracked)
+                        || repo_tag
+                            .rsplit_once(':')
+                            .map_or(false, |(repo, _)| self.repositories.contains(repo))
+                })
+            });
+        let layers = futures_util::stream::iter(summaries)
+            .map(|summary| async move {
+                let inspect = images.get(summary.id.unwrap_or_default()).inspect().await.d()?;
+                miette::Result::<_>::Ok(inspect.root_fs.and_then(|root_fs| root_fs.layers).unwrap_or_default())
+            })
+            .buffer_unordered(8)
+            .try_concat()
+            .await?;
+
+        Ok(layers.into_iter().collect())
     }
 }
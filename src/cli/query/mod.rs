@@ -1,5 +1,6 @@
 mod container;
 mod network;
+mod pod;
 pub mod volume;
 
 use clap::Parser;
@@ -16,6 +17,7 @@ pub struct Args {
 pub enum Subcommand {
     Container(container::Args),
     Network(network::Args),
+    Pod(pod::Args),
     Volume(volume::Args),
 }
 
@@ -24,6 +26,7 @@ impl Args {
         match self.subcommand {
             Subcommand::Container(args) => args.execute(config, logger).await,
             Subcommand::Network(args) => args.execute(config, logger).await,
+            Subcommand::Pod(args) => args.execute(config, logger).await,
             Subcommand::Volume(args) => args.execute(config, logger).await,
         }
     }
@@ -0,0 +1,20 @@
+use clap::Parser;
+
+use crate::{config::Config, logger::Logger};
+
+#[derive(Parser)]
+pub struct Args {
+    name: String,
+}
+
+impl Args {
+    pub async fn execute(self, config: Config, logger: Logger) -> miette::Result<()> {
+        let service = config.service(&logger, true).await?;
+        let pods = crate::plan::pod::remote_pod_query(&service, config.group, self.name).await?;
+        for pod in pods {
+            println!("{}", pod.id.unwrap());
+        }
+
+        Ok(())
+    }
+}
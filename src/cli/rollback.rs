@@ -0,0 +1,102 @@
+use std::path::PathBuf;
+
+use clap::Parser;
+
+use super::sync;
+use crate::{
+    config::Config,
+    lock::{Lock, LockedImage},
+    logger::Logger,
+    plan::Interrupts,
+    utils::IntoDiagnosticShorthand,
+};
+
+#[derive(Parser)]
+pub struct Args {
+    /// Where injected files and secrets are read from, like for sync
+    directory: PathBuf,
+    /// The revision to go back to [default: the one before the current one]
+    revision: Option<u64>,
+    /// Roll back even if injected files or secrets changed since the revision
+    #[arg(long)]
+    force: bool,
+}
+
+impl Args {
+    pub async fn execute(self, config: Config, logger: Logger, interrupts: &Interrupts) -> miette::Result<()> {
+        let sync = sync::Args::new(self.directory.clone());
+        sync.setup(&logger);
+
+        let service = config.service(&logger, false).await?;
+        let revisions = crate::history::list(&service, &config.raw_service(), &config.group).await?;
+        let revision = match self.revision {
+            Some(number) => revisions.into_iter().find(|(_, revision)| revision.number == number),
+            None => revisions.into_iter().rev().nth(1),
+        };
+        let Some((_, revision)) = revision else {
+            miette::bail!(help = "see `tug history` for what there is", "no revision to roll back to");
+        };
+        logger.info(format!("Rolling back to revision {}", revision.number));
+
+        // the documents get parsed from disk so errors can point into them
+        let root = tempfile::Builder::new().prefix("tug-revision-").tempdir().d()?;
+        for (path, text) in &revision.sources {
+            let path = root.path().join(path);
+            if let Some(parent) = path.parent() {
+                std::fs::create_dir_all(parent).d()?;
+            }
+            std::fs::write(path, text).d()?;
+        }
+
+        let result = async {
+            let document = crate::parse::parse_sources(&logger, root.path(), &revision.sources)?;
+            let inputs = crate::history::inputs(&self.directory, &config.group, &document);
+            let changed = revision
+                .inputs
+                .iter()
+                .filter(|(key, fingerprint)| inputs.get(*key) != Some(fingerprint))
+                .map(|(key, _)| key.as_str())
+                .collect::<Vec<_>>();
+            if !changed.is_empty() {
+                let changed = changed.join(", ");
+                if !self.force {
+                    miette::bail!(
+                        help = "roll back anyway with --force",
+                        "these changed since revision {}: {changed}",
+                        revision.number
+                    );
+                }
+                logger.warn(format!(
+                    "Rolling back with different injected files or secrets than revision {} had: {changed}",
+                    revision.number
+                ));
+            }
+            // the images are pinned to exactly what they were, like tug.lock does
+            let lock = Lock {
+                images: document
+                    .images
+                    .iter()
+                    .filter_map(|image| {
+                        let digest = revision.images.get(image.name.as_str())?.clone();
+                        let reference = (*image.reference).clone();
+                        Some(((*image.name).clone(), LockedImage { reference, digest }))
+                    })
+                    .collect(),
+            };
+            sync.apply(
+                &config,
+                &logger,
+                interrupts,
+                revision.sources.clone(),
+                document,
+                Some(&lock),
+                Some(revision.number),
+            )
+            .await
+        }
+        .await;
+        let _ = root.close();
+
+        result
+    }
+}
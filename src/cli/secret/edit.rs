@@ -0,0 +1,74 @@
+use std::{
+    fs::OpenOptions,
+    io::Write,
+    path::{Path, PathBuf},
+};
+
+use clap::Parser;
+use miette::Context;
+
+use crate::{config::Config, logger::Logger, utils::IntoDiagnosticShorthand};
+
+#[derive(Parser)]
+pub struct Args {
+    file: PathBuf,
+}
+
+impl Args {
+    pub async fn execute(self, config: Config, logger: Logger) -> miette::Result<()> {
+        let plaintext = match tokio::fs::read(&self.file).await {
+            Ok(ciphertext) => crate::encryption::decrypt(&config.identity()?, &ciphertext)?,
+            Err(err) if err.kind() == std::io::ErrorKind::NotFound => Vec::new(),
+            Err(err) => return Err(err).d(),
+        };
+
+        // a private directory, so nobody else can guess the path or get at it
+        let directory = tempfile::Builder::new().prefix("tug-secret-").tempdir().d()?;
+        let temp = directory.path().join(self.file.file_name().unwrap_or("secret".as_ref()));
+        let mut options = OpenOptions::new();
+        options.write(true).create_new(true);
+        #[cfg(unix)]
+        std::os::unix::fs::OpenOptionsExt::mode(&mut options, 0o600);
+        options.open(&temp).d()?.write_all(&plaintext).d()?;
+
+        let edited = edit(&temp).await;
+        // best effort at not leaving plaintext lying around
+        let _ = tokio::fs::write(&temp, vec![0; plaintext.len().max(edited.as_ref().map_or(0, Vec::len))]).await;
+        let _ = directory.close();
+        let edited = edited?;
+
+        if edited == plaintext {
+            logger.info("No changes");
+            return Ok(());
+        }
+
+        let ciphertext = crate::encryption::encrypt(&config.recipients, &edited)?;
+        tokio::fs::write(&self.file, ciphertext).await.d()?;
+        logger.info(format!("Saved {}", self.file.display()));
+
+        Ok(())
+    }
+}
+
+async fn edit(path: &Path) -> miette::Result<Vec<u8>> {
+    let editor = std::env::var("VISUAL")
+        .or_else(|_| std::env::var("EDITOR"))
+        .unwrap_or_else(|_| "vi".to_string());
+    let Some(mut command) = shlex::split(&editor).filter(|command| !command.is_empty()) else {
+        return Err(miette::miette!("malformed editor command `{editor}`"));
+    };
+    let program = command.remove(0);
+
+    let status = tokio::process::Command::new(&program)
+        .args(command)
+        .arg(path)
+        .status()
+        .await
+        .d()
+        .wrap_err_with(|| format!("running editor `{program}`"))?;
+    if !status.success() {
+        return Err(miette::miette!("editor exited with {status}"));
+    }
+
+    tokio::fs::read(path).await.d()
+}
@@ -0,0 +1,30 @@
+use std::path::PathBuf;
+
+use clap::Parser;
+
+use crate::{config::Config, logger::Logger, utils::IntoDiagnosticShorthand};
+
+#[derive(Parser)]
+pub struct Args {
+    file: PathBuf,
+    #[arg(short, long)]
+    output: Option<PathBuf>,
+}
+
+impl Args {
+    pub async fn execute(self, config: Config, logger: Logger) -> miette::Result<()> {
+        let output = self.output.unwrap_or_else(|| {
+            let mut name = self.file.clone().into_os_string();
+            name.push(".age");
+            name.into()
+        });
+
+        let plaintext = tokio::fs::read(&self.file).await.d()?;
+        let ciphertext = crate::encryption::encrypt(&config.recipients, &plaintext)?;
+        tokio::fs::write(&output, ciphertext).await.d()?;
+        logger.info(format!("Encrypted {} to {}", self.file.display(), output.display()));
+        logger.info(format!("Don't forget to delete {}!", self.file.display()));
+
+        Ok(())
+    }
+}
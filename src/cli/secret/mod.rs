@@ -0,0 +1,27 @@
+mod edit;
+mod encrypt;
+
+use clap::Parser;
+
+use crate::{config::Config, logger::Logger};
+
+#[derive(Parser)]
+pub struct Args {
+    #[clap(subcommand)]
+    subcommand: Subcommand,
+}
+
+#[derive(Parser)]
+pub enum Subcommand {
+    Edit(edit::Args),
+    Encrypt(encrypt::Args),
+}
+
+impl Args {
+    pub async fn execute(self, config: Config, logger: Logger) -> miette::Result<()> {
+        match self.subcommand {
+            Subcommand::Edit(args) => args.execute(config, logger).await,
+            Subcommand::Encrypt(args) => args.execute(config, logger).await,
+        }
+    }
+}
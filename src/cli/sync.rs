@@ -1,23 +1,152 @@
-use std::path::PathBuf;
+use std::{collections::BTreeMap, path::PathBuf, time::Duration};
 
-use clap::Parser;
+use clap::{Parser, ValueEnum};
 
-use crate::{config::Config, logger::Logger, plan::Executor};
+use crate::{
+    config::Config,
+    lock::Lock,
+    logger::Logger,
+    parse::model::ParsedDocument,
+    plan::{Executor, Interrupts},
+    utils::IntoDiagnosticShorthand,
+};
 
 #[derive(Parser)]
 pub struct Args {
     directory: PathBuf,
+    /// How many steps to run at once [default: 5]
+    #[arg(long, value_parser = clap::value_parser!(u16).range(1..))]
+    parallelism: Option<u16>,
+    /// How many seconds a single step may take before it fails [default: 900]
+    #[arg(long, value_name = "SECONDS", value_parser = clap::value_parser!(u64).range(1..))]
+    step_timeout: Option<u64>,
+    /// Keep running steps that don't depend on a failed one, and only roll back
+    /// what failed
+    #[arg(long)]
+    keep_going: bool,
+    /// Pull every image before running any steps
+    #[arg(long)]
+    pre_pull: bool,
+    /// Remove unused images afterwards, keeping this many old versions of each
+    #[arg(long, value_name = "KEEP", num_args = 0..=1, default_missing_value = "2")]
+    gc_images: Option<usize>,
+    /// Fail instead of warning when tug.lock doesn't match the configs
+    #[arg(long)]
+    locked: bool,
+    /// How to print the report at the end
+    #[arg(long, value_enum, default_value_t = Output::Table)]
+    output: Output,
+}
+
+#[derive(ValueEnum, Clone, Copy, PartialEq, Eq)]
+enum Output {
+    Table,
+    Json,
 }
 
 impl Args {
-    pub async fn execute(self, config: Config, logger: Logger) -> miette::Result<()> {
-        let document = crate::parse::parse(&logger, &self.directory)?;
+    // what `tug sync <directory>` would do with no flags
+    pub fn new(directory: PathBuf) -> Args {
+        Args {
+            directory,
+            parallelism: None,
+            step_timeout: None,
+            keep_going: false,
+            pre_pull: false,
+            gc_images: None,
+            locked: false,
+            output: Output::Table,
+        }
+    }
+
+    pub async fn execute(self, config: Config, logger: Logger, interrupts: &Interrupts) -> miette::Result<()> {
+        self.setup(&logger);
+
+        let sources = crate::parse::sources(&self.directory)?;
+        let document = crate::parse::parse_sources(&logger, &self.directory, &sources)?;
+        let lock = crate::lock::read(&self.directory)?;
+        if self.locked && lock.is_none() {
+            miette::bail!(help = "run `tug lock` first", "--locked needs a {}", crate::lock::LOCK_FILE);
+        }
+        self.apply(&config, &logger, interrupts, sources, document, lock.as_ref(), None)
+            .await
+    }
+
+    // has to happen before anything gets logged
+    pub fn setup(&self, logger: &Logger) {
+        if self.output == Output::Json {
+            logger.use_stderr();
+        }
+        logger.enable_progress();
+    }
+
+    // everything after parsing, shared with rollback which brings its own
+    // documents and images
+    #[allow(clippy::too_many_arguments)]
+    pub async fn apply(
+        &self,
+        config: &Config,
+        logger: &Logger,
+        interrupts: &Interrupts,
+        sources: BTreeMap<PathBuf, String>,
+        document: ParsedDocument,
+        lock: Option<&Lock>,
+        rollback_of: Option<u64>,
+    ) -> miette::Result<()> {
         let mut executor = Executor::new();
-        crate::prepare::prepare(&logger, document, &mut executor)?;
-        let service = config.service(&logger, false).await?;
-        logger.log("Executing plan");
-        executor.execute(&config, &logger, service, &self.directory).await?;
-        logger.log("Done!");
+        executor.keep_going = self.keep_going;
+        if let Some(parallelism) = self.parallelism {
+            executor.parallelism = parallelism.into();
+        }
+        if let Some(step_timeout) = self.step_timeout {
+            executor.step_timeout = Duration::from_secs(step_timeout);
+        }
+        let inputs = crate::history::inputs(&self.directory, &config.group, &document);
+        crate::prepare::prepare(logger, document, lock, self.locked, &mut executor)?;
+        let service = config.service(logger, false).await?;
+        if self.pre_pull {
+            logger.info("Pulling images");
+            let pulled = crate::plan::image::pre_pull(
+                &service,
+                &config.credentials(),
+                logger,
+                executor.image_actions(),
+                executor.parallelism,
+            )
+            .await?;
+            executor.set_pre_pulled(pulled);
+        }
+        logger.info("Executing plan");
+        let report = executor
+            .execute(config, logger, service.clone(), &self.directory, interrupts)
+            .await?;
+        match self.output {
+            Output::Table => print!("{report}"),
+            Output::Json => println!("{}", serde_json::to_string(&report).d()?),
+        }
+        if !report.succeeded {
+            miette::bail!("sync failed");
+        }
+        // the deploy itself went fine, it just can't be rolled back to
+        match crate::history::record(
+            &service,
+            &config.raw_service(),
+            &config.group,
+            sources,
+            report.images,
+            inputs,
+            rollback_of,
+        )
+        .await
+        {
+            Ok(revision) => logger.info(format!("Recorded as revision {revision}")),
+            Err(err) => logger.warn(format!("Couldn't record this sync in the history: {err}")),
+        }
+        if let Some(keep) = self.gc_images {
+            logger.info("Removing unused images");
+            crate::plan::image::collect_garbage(&service, &config.group, logger, keep).await?;
+        }
+        logger.info("Done!");
 
         Ok(())
     }
@@ -1,4 +1,7 @@
-use std::path::{Path, PathBuf};
+use std::{
+    collections::BTreeMap,
+    path::{Path, PathBuf},
+};
 
 use figment::{
     providers::{Env, Format, Toml},
@@ -7,19 +10,42 @@ use figment::{
 use podman_api::Podman;
 use serde::Deserialize;
 
-use crate::{logger::Logger, utils::IntoDiagnosticShorthand};
+use crate::{
+    logger::Logger,
+    registry::{Credentials, Registry},
+    utils::{IntoDiagnosticShorthand, RawService},
+};
 
 #[derive(Deserialize, Debug)]
 pub struct Config {
     service: String,
     #[serde(default = "default_group")]
     pub group: String,
+    #[serde(default)]
+    pub recipients: Vec<String>,
+    // falls back to default_identity when unset
+    pub identity: Option<PathBuf>,
+    // credentials per registry host, or per repository to narrow them down
+    #[serde(default)]
+    pub registries: BTreeMap<String, Registry>,
+    // podman's auth.json, looked up the way podman does when unset
+    pub auth_file: Option<PathBuf>,
 }
 
 fn default_group() -> String {
     "default".into()
 }
 
+pub fn default_identity() -> miette::Result<PathBuf> {
+    match dirs::config_dir() {
+        Some(config_dir) => Ok(config_dir.join("tug-identity.txt")),
+        None => miette::bail!(
+            help = "set `identity` in tug.toml to where your age identity is",
+            "can't find a config directory to look for tug-identity.txt in"
+        ),
+    }
+}
+
 pub fn config_file() -> PathBuf {
     match std::env::var("TUG_CONFIG") {
         Ok(config_file) => Path::new(&config_file).to_path_buf(),
@@ -37,9 +63,27 @@ pub fn load() -> Result<Config, figment::Error> {
 impl Config {
     pub async fn service(&self, logger: &Logger, silent: bool) -> miette::Result<Podman> {
         if !silent {
-            logger.log("Connecting to container runtime");
+            logger.info("Connecting to container runtime");
         }
         let service = Podman::new(&self.service).d()?;
         Ok(service)
     }
+
+    pub fn identity(&self) -> miette::Result<PathBuf> {
+        match &self.identity {
+            Some(identity) => Ok(identity.clone()),
+            None => default_identity(),
+        }
+    }
+
+    pub fn credentials(&self) -> Credentials {
+        Credentials {
+            registries: self.registries.clone(),
+            auth_file: self.auth_file.clone(),
+        }
+    }
+
+    pub fn raw_service(&self) -> RawService {
+        RawService::new(&self.service)
+    }
 }
@@ -0,0 +1,65 @@
+// age encryption for secrets that live next to the configuration documents
+
+use std::{
+    io::{Read, Write},
+    path::Path,
+    str::FromStr,
+};
+
+use age::{
+    armor::{ArmoredReader, ArmoredWriter, Format},
+    x25519, Decryptor, Encryptor, IdentityFile, IdentityFileEntry,
+};
+use miette::Context;
+
+use crate::utils::IntoDiagnosticShorthand;
+
+pub fn encrypt(recipients: &[String], plaintext: &[u8]) -> miette::Result<Vec<u8>> {
+    let mut parsed: Vec<Box<dyn age::Recipient + Send>> = Vec::with_capacity(recipients.len());
+    for recipient in recipients {
+        match x25519::Recipient::from_str(recipient) {
+            Ok(recipient) => parsed.push(Box::new(recipient)),
+            Err(err) => return Err(miette::miette!("invalid recipient `{recipient}`: {err}")),
+        }
+    }
+    let Some(encryptor) = Encryptor::with_recipients(parsed) else {
+        return Err(miette::miette!(
+            help = "add age public keys to the `recipients` list in your tug config",
+            "no recipients configured"
+        ));
+    };
+
+    let mut output = Vec::new();
+    let armored = ArmoredWriter::wrap_output(&mut output, Format::AsciiArmor).d()?;
+    let mut writer = encryptor.wrap_output(armored).d()?;
+    writer.write_all(plaintext).d()?;
+    writer.finish().d()?.finish().d()?;
+
+    Ok(output)
+}
+
+pub fn decrypt(identity: &Path, ciphertext: &[u8]) -> miette::Result<Vec<u8>> {
+    let identities = IdentityFile::from_file(identity.to_string_lossy().into_owned())
+        .d()
+        .wrap_err_with(|| format!("reading identity file {identity:?}"))?
+        .into_identities()
+        .into_iter()
+        .map(|entry| match entry {
+            IdentityFileEntry::Native(identity) => identity,
+        })
+        .collect::<Vec<_>>();
+
+    let decryptor = match Decryptor::new(ArmoredReader::new(ciphertext)).d()? {
+        Decryptor::Recipients(decryptor) => decryptor,
+        Decryptor::Passphrase(_) => return Err(miette::miette!("passphrase-encrypted files aren't supported")),
+    };
+
+    let mut plaintext = Vec::new();
+    decryptor
+        .decrypt(identities.iter().map(|identity| identity as &dyn age::Identity))
+        .d()?
+        .read_to_end(&mut plaintext)
+        .d()?;
+
+    Ok(plaintext)
+}
@@ -0,0 +1,194 @@
+// every successful sync is recorded as a revision so it can be rolled back to.
+// they live in secrets, which podman holds arbitrary data in without a
+// container and, unlike volumes, never prunes
+
+use std::{
+    collections::{BTreeMap, HashMap},
+    path::{Path, PathBuf},
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+use base64::{prelude::BASE64_URL_SAFE_NO_PAD, Engine};
+use hyper::{Body, Method};
+use podman_api::Podman;
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+
+use crate::{
+    parse::model::{ParsedContainerInject, ParsedDocument},
+    plan::Retry,
+    utils::{IntoDiagnosticShorthand, RawService, XTug},
+};
+
+// older revisions get deleted once there are more than this
+pub const HISTORY_LENGTH: usize = 20;
+
+// podman won't take a secret any bigger
+const MAX_SECRET_SIZE: usize = 512_000;
+
+#[derive(Serialize, Deserialize, Debug)]
+pub struct Revision {
+    #[serde(rename = "n")]
+    pub number: u64,
+    #[serde(rename = "t")]
+    pub time: u64,
+    // the config documents by path relative to the synced directory
+    #[serde(rename = "d")]
+    pub sources: BTreeMap<PathBuf, String>,
+    // the image id every image name resolved to
+    #[serde(rename = "i")]
+    pub images: BTreeMap<String, String>,
+    #[serde(rename = "r")]
+    pub rollback_of: Option<u64>,
+    // fingerprints of the injected files and secrets, see `inputs`
+    #[serde(rename = "f", default)]
+    pub inputs: BTreeMap<String, String>,
+}
+
+// oldest first, with the id of the secret holding each
+pub async fn list(service: &Podman, raw_service: &RawService, group: &str) -> miette::Result<Vec<(String, Revision)>> {
+    let secrets = service.secrets();
+    let mut revisions = Vec::new();
+    for secret in Retry::QUERY.run(|| secrets.list()).await.d()? {
+        let labels = secret.spec.and_then(|spec| spec.labels).unwrap_or_default();
+        let Some(id) = secret.id.filter(|_| labels.get(XTug::History.as_ref()) == Some(&group.to_string())) else {
+            continue;
+        };
+        // podman-api can't ask for the content, the raw api can
+        let inspect = raw_service
+            .request(
+                Method::GET,
+                &format!("/libpod/secrets/{id}/json?showsecret=true"),
+                Body::empty(),
+            )
+            .await?;
+        let revision = serde_json::from_slice::<serde_json::Value>(&inspect)
+            .ok()
+            .and_then(|inspect| BASE64_URL_SAFE_NO_PAD.decode(inspect.get("SecretData")?.as_str()?).ok())
+            .and_then(|revision| rmp_serde::from_slice::<Revision>(&revision).ok());
+        if let Some(revision) = revision {
+            revisions.push((id, revision));
+        }
+    }
+    revisions.sort_by_key(|(_, revision)| revision.number);
+
+    Ok(revisions)
+}
+
+pub async fn record(
+    service: &Podman,
+    raw_service: &RawService,
+    group: &str,
+    sources: BTreeMap<PathBuf, String>,
+    images: BTreeMap<String, String>,
+    inputs: BTreeMap<String, String>,
+    rollback_of: Option<u64>,
+) -> miette::Result<u64> {
+    let mut revisions = list(service, raw_service, group).await?;
+    let revision = Revision {
+        number: revisions.last().map_or(1, |(_, revision)| revision.number + 1),
+        time: SystemTime::now().duration_since(UNIX_EPOCH).unwrap_or_default().as_secs(),
+        sources,
+        images,
+        rollback_of,
+        inputs,
+    };
+
+    // podman-api would send the secret's content as a json string, so this
+    // goes around it like secret steps do
+    let encoded = BASE64_URL_SAFE_NO_PAD.encode(rmp_serde::to_vec(&revision).d()?);
+    if encoded.len() > MAX_SECRET_SIZE {
+        miette::bail!(
+            "revision {} is {} bytes, more than the {MAX_SECRET_SIZE} a podman secret can hold",
+            revision.number,
+            encoded.len()
+        );
+    }
+    let number = revision.number.to_string();
+    let labels = HashMap::from([(XTug::History.as_ref(), group), (XTug::Revision.as_ref(), number.as_str())]);
+    let name = Sha256::new().chain_update(group).chain_update(&encoded).finalize();
+    let query = url::form_urlencoded::Serializer::new(String::new())
+        .append_pair("name", &format!("tug-revision-{}", BASE64_URL_SAFE_NO_PAD.encode(name)))
+        .append_pair("labels", &serde_json::to_string(&labels).d()?)
+        .finish();
+    raw_service
+        .request(Method::POST, &format!("/libpod/secrets/create?{query}"), Body::from(encoded))
+        .await?;
+
+    // the new one isn't in the list, so one less than the limit stays
+    let excess = (revisions.len() + 1).saturating_sub(HISTORY_LENGTH);
+    for (id, _) in revisions.drain(..excess) {
+        service.secrets().get(id).delete().await.d()?;
+    }
+
+    Ok(revision.number)
+}
+
+// a digest of every injected file and secret the document reads, so a rollback
+// can tell whether they're still what that revision ran with. keyed by group
+// like secret salts are, the labels aren't a lookup table for short secrets.
+// secrets from commands aren't run just for this, and are left out
+pub fn inputs(root: &Path, group: &str, document: &ParsedDocument) -> BTreeMap<String, String> {
+    let injects = document
+        .containers
+        .iter()
+        .flat_map(|container| &container.injects)
+        .chain(document.jobs.iter().flat_map(|job| &job.injects))
+        .chain(document.schedules.iter().flat_map(|schedule| &schedule.injects));
+
+    let mut inputs = BTreeMap::new();
+    for ParsedContainerInject { path, .. } in injects {
+        let key = format!("inject {}", path.display());
+        inputs.insert(key.clone(), fingerprint(group, &key, path_content(&root.join(path))));
+    }
+    for secret in &document.secrets {
+        let content = if let Some(path) = secret.from_file.as_ref().or(secret.from_age.as_ref()) {
+            std::fs::read(root.join(path)).ok()
+        } else if let Some(variable) = &secret.from_env {
+            std::env::var_os(variable).map(crate::utils::os_string_vec)
+        } else {
+            continue;
+        };
+        let key = format!("secret {}", *secret.name);
+        inputs.insert(key.clone(), fingerprint(group, &key, content));
+    }
+
+    inputs
+}
+
+fn fingerprint(group: &str, key: &str, content: Option<Vec<u8>>) -> String {
+    match content {
+        Some(content) => {
+            let digest = Sha256::new()
+                .chain_update(b"tug-revision-input\0")
+                .chain_update(group)
+                .chain_update(b"\0")
+                .chain_update(key)
+                .chain_update(b"\0")
+                .chain_update(content)
+                .finalize();
+            BASE64_URL_SAFE_NO_PAD.encode(digest)
+        }
+        None => "missing".to_string(),
+    }
+}
+
+// a file's content, or every path and content in a directory
+fn path_content(path: &Path) -> Option<Vec<u8>> {
+    if !path.is_dir() {
+        return std::fs::read(path).ok();
+    }
+    let mut content = Vec::new();
+    for entry in walkdir::WalkDir::new(path).sort_by_file_name() {
+        let entry = entry.ok()?;
+        let relative = entry.path().strip_prefix(path).ok()?;
+        content.extend(crate::utils::os_string_vec(relative.as_os_str().to_owned()));
+        content.push(0);
+        if entry.file_type().is_file() {
+            let file = std::fs::read(entry.path()).ok()?;
+            content.extend((file.len() as u64).to_le_bytes());
+            content.extend(file);
+        }
+    }
+    Some(content)
+}
@@ -0,0 +1,102 @@
+// tug.lock pins image references to the digests they pointed at when it was
+// written, so every sync runs exactly the same images
+
+use std::{
+    collections::BTreeMap,
+    io,
+    path::{Path, PathBuf},
+};
+
+use miette::Context;
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    registry::{self, Credentials},
+    utils::IntoDiagnosticShorthand,
+};
+
+pub const LOCK_FILE: &str = "tug.lock";
+
+#[derive(Serialize, Deserialize, Default, Debug)]
+pub struct Lock {
+    pub images: BTreeMap<String, LockedImage>,
+}
+
+#[derive(Serialize, Deserialize, Clone, PartialEq, Eq, Debug)]
+pub struct LockedImage {
+    pub reference: String,
+    pub digest: String,
+}
+
+impl Lock {
+    // the digest an image is pinned to, or why it isn't
+    pub fn pin(&self, name: &str, reference: &str) -> Result<&str, &'static str> {
+        match self.images.get(name) {
+            Some(locked) if locked.reference == reference => Ok(&locked.digest),
+            Some(_) => Err("has changed since"),
+            None => Err("is missing from"),
+        }
+    }
+}
+
+pub fn lock_path(directory: &Path) -> PathBuf {
+    directory.join(LOCK_FILE)
+}
+
+pub fn read(directory: &Path) -> miette::Result<Option<Lock>> {
+    let path = lock_path(directory);
+    match std::fs::read_to_string(&path) {
+        Ok(content) => Ok(Some(
+            serde_json::from_str(&content)
+                .d()
+                .wrap_err_with(|| format!("reading {}", path.display()))?,
+        )),
+        Err(err) if err.kind() == io::ErrorKind::NotFound => Ok(None),
+        Err(err) => Err(err).d(),
+    }
+}
+
+pub fn write(directory: &Path, lock: &Lock) -> miette::Result<()> {
+    let mut content = serde_json::to_string_pretty(lock).d()?;
+    content.push('\n');
+    std::fs::write(lock_path(directory), content).d()
+}
+
+// asks the registry what the reference points at now, without pulling it
+pub async fn resolve_digest(credentials: &Credentials, reference: &str) -> miette::Result<String> {
+    registry::manifest_digest(credentials, reference)
+        .await
+        .wrap_err_with(|| format!("resolving `{reference}`"))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn lock() -> Lock {
+        Lock {
+            images: BTreeMap::from([(
+                "redis".to_string(),
+                LockedImage {
+                    reference: "redis:7".to_string(),
+                    digest: "docker.io/library/redis@sha256:abc".to_string(),
+                },
+            )]),
+        }
+    }
+
+    #[test]
+    fn pinned() {
+        assert_eq!(lock().pin("redis", "redis:7"), Ok("docker.io/library/redis@sha256:abc"));
+    }
+
+    #[test]
+    fn stale() {
+        assert_eq!(lock().pin("redis", "redis:8"), Err("has changed since"));
+    }
+
+    #[test]
+    fn missing() {
+        assert_eq!(lock().pin("postgres", "postgres:16"), Err("is missing from"));
+    }
+}
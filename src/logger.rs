@@ -1,36 +1,282 @@
 // basic hierarchical logger
 
-use std::{fmt::Display, io::Write, sync::Arc};
+use std::{
+    fmt::Display,
+    fs::File,
+    io::{IsTerminal, Write},
+    sync::Arc,
+    time::{Duration, SystemTime, UNIX_EPOCH},
+};
 
+use clap::ValueEnum;
+use indicatif::{MultiProgress, ProgressBar, ProgressDrawTarget, ProgressStyle};
 use parking_lot::Mutex;
 
+#[derive(Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Debug)]
+pub enum Level {
+    Error,
+    Warn,
+    Info,
+    Debug,
+    Trace,
+}
+
+impl Level {
+    // every -v goes one level up from info, every -q one down
+    pub fn from_verbosity(verbose: u8, quiet: u8) -> Level {
+        match 2 + verbose as i16 - quiet as i16 {
+            i16::MIN..=0 => Level::Error,
+            1 => Level::Warn,
+            2 => Level::Info,
+            3 => Level::Debug,
+            _ => Level::Trace,
+        }
+    }
+
+    fn as_str(self) -> &'static str {
+        match self {
+            Level::Error => "error",
+            Level::Warn => "warn",
+            Level::Info => "info",
+            Level::Debug => "debug",
+            Level::Trace => "trace",
+        }
+    }
+}
+
+#[derive(ValueEnum, Clone, Copy, PartialEq, Eq, Debug)]
+pub enum LogFormat {
+    Text,
+    Json,
+}
+
 #[derive(Clone)]
 pub struct Logger {
     inner: Arc<Mutex<LoggerInner>>,
+    // context for everything logged through this handle, see for_step
+    step: Option<usize>,
+    resource: Option<String>,
 }
 
 struct LoggerInner {
-    trace: bool,
+    level: Level,
+    format: LogFormat,
+    // keeps stdout clean for machine readable output
+    stderr: bool,
+    file: Option<File>,
+    // lines get printed above the progress bars while they're up
+    progress: Option<MultiProgress>,
+}
+
+impl LoggerInner {
+    // warnings and errors always go to stderr, like every other tool's
+    fn write(&mut self, level: Level, line: String) {
+        if let Some(file) = &mut self.file {
+            let _ = writeln!(file, "{line}");
+        }
+
+        let stderr = self.stderr || level <= Level::Warn;
+        let print = || {
+            if stderr {
+                let _ = std::io::stderr().lock().write_all(format!("{line}\n").as_bytes());
+            } else {
+                let _ = std::io::stdout().lock().write_all(format!("{line}\n").as_bytes());
+            }
+        };
+        match &self.progress {
+            Some(progress) if stderr => progress.suspend(print),
+            Some(progress) => {
+                let _ = progress.println(&line);
+            }
+            None => print(),
+        }
+    }
 }
 
 impl Logger {
-    pub fn new() -> Logger {
+    pub fn new(level: Level, format: LogFormat, file: Option<File>) -> Logger {
         Logger {
             inner: Arc::new(Mutex::new(LoggerInner {
-                trace: std::env::var("TUG_TRACE").is_ok(),
+                // still honoured so old habits keep working
+                level: if std::env::var("TUG_TRACE").is_ok() {
+                    Level::Trace
+                } else {
+                    level
+                },
+                format,
+                stderr: false,
+                file,
+                progress: None,
             })),
+            step: None,
+            resource: None,
         }
     }
 
-    pub fn log(&self, d: impl Display) {
-        let _ = std::io::stdout().lock().write_all(format!("{d}\n").as_bytes());
+    // a handle that tags everything it logs with the step and what it's working on
+    pub fn for_step(&self, step: usize, resource: impl Display) -> Logger {
+        Logger {
+            inner: self.inner.clone(),
+            step: Some(step),
+            resource: Some(resource.to_string()),
+        }
+    }
+
+    // the same, for work that isn't a step of a plan
+    pub fn for_resource(&self, resource: impl Display) -> Logger {
+        Logger {
+            inner: self.inner.clone(),
+            step: None,
+            resource: Some(resource.to_string()),
+        }
+    }
+
+    pub fn use_stderr(&self) {
+        self.inner.lock().stderr = true;
+    }
+
+    // only takes effect when a person is watching, anything else gets plain lines
+    pub fn enable_progress(&self) {
+        let mut inner = self.inner.lock();
+        if !inner.stderr && inner.format == LogFormat::Text && inner.level < Level::Trace && std::io::stdout().is_terminal() {
+            inner.progress = Some(MultiProgress::with_draw_target(ProgressDrawTarget::stdout()));
+        }
+    }
+
+    pub fn interactive(&self) -> bool {
+        self.inner.lock().progress.is_some()
+    }
+
+    pub fn error(&self, d: impl Display) {
+        self.emit(Level::Error, d);
+    }
+
+    // the full report for people, just the chain of causes on one line for
+    // anything reading json
+    pub fn failure(&self, err: &miette::Report) {
+        let format = self.inner.lock().format;
+        match format {
+            LogFormat::Text => self.error(format!("{err:?}")),
+            LogFormat::Json => self.error(err.chain().map(ToString::to_string).collect::<Vec<_>>().join(": ")),
+        }
+    }
+
+    pub fn warn(&self, d: impl Display) {
+        self.emit(Level::Warn, d);
+    }
+
+    pub fn info(&self, d: impl Display) {
+        self.emit(Level::Info, d);
+    }
+
+    pub fn debug(&self, d: impl Display) {
+        self.emit(Level::Debug, d);
     }
 
     pub fn trace(&self, d: impl Display) {
-        let inner = self.inner.lock();
-        if !inner.trace {
+        self.emit(Level::Trace, d);
+    }
+
+    fn emit(&self, level: Level, d: impl Display) {
+        let mut inner = self.inner.lock();
+        if level > inner.level {
             return;
         }
-        let _ = std::io::stdout().lock().write_all(format!("[TRACE] {d}\n").as_bytes());
+
+        let line = match inner.format {
+            LogFormat::Text => {
+                let prefix = match level {
+                    Level::Error => "error: ",
+                    Level::Warn => "warning: ",
+                    Level::Info => "",
+                    Level::Debug => "[DEBUG] ",
+                    Level::Trace => "[TRACE] ",
+                };
+                match &self.resource {
+                    Some(resource) => format!("{prefix}[{resource}] {d}"),
+                    None => format!("{prefix}{d}"),
+                }
+            }
+            LogFormat::Json => {
+                let mut entry = serde_json::Map::new();
+                let time = SystemTime::now().duration_since(UNIX_EPOCH).unwrap_or_default();
+                entry.insert("time".into(), time.as_secs_f64().into());
+                entry.insert("level".into(), level.as_str().into());
+                if let Some(step) = self.step {
+                    entry.insert("step".into(), step.into());
+                }
+                if let Some(resource) = &self.resource {
+                    entry.insert("resource".into(), resource.as_str().into());
+                }
+                entry.insert("message".into(), d.to_string().into());
+                serde_json::Value::Object(entry).to_string()
+            }
+        };
+        inner.write(level, line);
+    }
+
+    pub fn spinner(&self, prefix: impl Display) -> Progress {
+        let style = ProgressStyle::with_template("{spinner} {prefix} {wide_msg:.dim}").expect("template should be valid");
+        let bar = self.add(ProgressBar::new_spinner().with_style(style).with_prefix(prefix.to_string()));
+        if let Some(bar) = &bar {
+            bar.enable_steady_tick(Duration::from_millis(100));
+        }
+        Progress { bar }
+    }
+
+    pub fn counter(&self, length: u64, unit: &str) -> Progress {
+        let style = ProgressStyle::with_template(&format!("[{{bar:30}}] {{pos}}/{{len}} {unit} {{msg}}"))
+            .expect("template should be valid")
+            .progress_chars("=> ");
+        Progress {
+            bar: self.add(ProgressBar::new(length).with_style(style)),
+        }
+    }
+
+    fn add(&self, bar: ProgressBar) -> Option<ProgressBar> {
+        self.inner.lock().progress.as_ref().map(|progress| progress.add(bar))
+    }
+}
+
+// a progress bar that does nothing when progress isn't enabled
+#[derive(Clone)]
+pub struct Progress {
+    bar: Option<ProgressBar>,
+}
+
+impl Progress {
+    pub fn set_message(&self, message: impl Display) {
+        if let Some(bar) = &self.bar {
+            bar.set_message(message.to_string());
+        }
+    }
+
+    pub fn inc(&self) {
+        if let Some(bar) = &self.bar {
+            bar.inc(1);
+        }
+    }
+
+    pub fn finish(&self) {
+        if let Some(bar) = &self.bar {
+            bar.finish_and_clear();
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::Level;
+
+    #[test]
+    fn verbosity() {
+        assert_eq!(Level::from_verbosity(0, 0), Level::Info);
+        assert_eq!(Level::from_verbosity(1, 0), Level::Debug);
+        assert_eq!(Level::from_verbosity(2, 0), Level::Trace);
+        assert_eq!(Level::from_verbosity(9, 0), Level::Trace);
+        assert_eq!(Level::from_verbosity(0, 1), Level::Warn);
+        assert_eq!(Level::from_verbosity(0, 2), Level::Error);
+        assert_eq!(Level::from_verbosity(0, 9), Level::Error);
+        assert_eq!(Level::from_verbosity(1, 1), Level::Info);
     }
 }
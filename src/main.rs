@@ -1,19 +1,33 @@
 use clap::Parser;
-use logger::Logger;
 use utils::IntoDiagnosticShorthand;
 
 mod cli;
 mod config;
+mod encryption;
+mod history;
+mod lock;
 mod logger;
 mod parse;
 mod plan;
 mod prepare;
+mod registry;
 mod utils;
 
 #[tokio::main(flavor = "current_thread")]
 async fn main() -> miette::Result<()> {
-    let logger = Logger::new();
-    let config = config::load().d()?;
     let args = cli::Args::parse();
-    args.execute(config, logger).await
+    let logger = args.logger()?;
+    let config = config::load().d()?;
+    let interrupts = plan::Interrupts::new().d()?;
+    let mut unhandled = interrupts.clone();
+    // dropping the command on the way out still cleans up after it, unlike exiting
+    // from inside it
+    let result = tokio::select! {
+        result = args.execute(config, logger, &interrupts) => Some(result),
+        _ = unhandled.unhandled() => None,
+    };
+    match result {
+        Some(result) => result,
+        None => std::process::exit(130),
+    }
 }
@@ -1,4 +1,7 @@
-use std::path::Path;
+use std::{
+    collections::BTreeMap,
+    path::{Path, PathBuf},
+};
 
 use walkdir::WalkDir;
 
@@ -9,10 +12,13 @@ pub mod model;
 pub mod span;
 
 pub fn parse(logger: &Logger, root: &Path) -> miette::Result<ParsedDocument> {
-    logger.log("Parsing configuration documents");
-
-    let mut merged = ParsedDocument::default();
+    parse_sources(logger, root, &sources(root)?)
+}
 
+// every config document under root by its path relative to root, kept around so
+// a sync can be recorded and replayed later
+pub fn sources(root: &Path) -> miette::Result<BTreeMap<PathBuf, String>> {
+    let mut sources = BTreeMap::new();
     for ent in WalkDir::new(root).follow_links(true) {
         let ent = ent.d()?;
         let file_name = ent.file_name().to_str().expect("paths should be unicode");
@@ -20,12 +26,38 @@ pub fn parse(logger: &Logger, root: &Path) -> miette::Result<ParsedDocument> {
             continue;
         }
         let text = std::fs::read_to_string(ent.path()).d()?;
-        let doc: ParsedDocument =
-            knuffel::parse_with_context(file_name, &text, |ctx| ctx.set(FilePath(ent.path().to_path_buf())))?;
+        // a root that's a single document is its own only entry
+        let path = match ent.path().strip_prefix(root) {
+            Ok(path) if !path.as_os_str().is_empty() => path,
+            _ => Path::new(ent.file_name()),
+        };
+        sources.insert(path.to_path_buf(), text);
+    }
+
+    Ok(sources)
+}
+
+pub fn parse_sources(logger: &Logger, root: &Path, sources: &BTreeMap<PathBuf, String>) -> miette::Result<ParsedDocument> {
+    logger.info("Parsing configuration documents");
+
+    let mut merged = ParsedDocument::default();
+
+    for (path, text) in sources {
+        let file_name = path
+            .file_name()
+            .and_then(|name| name.to_str())
+            .expect("paths should be unicode");
+        let full_path = if root.is_dir() { root.join(path) } else { root.to_path_buf() };
+        let doc: ParsedDocument = knuffel::parse_with_context(file_name, text, |ctx| ctx.set(FilePath(full_path)))?;
         merged.containers.extend(doc.containers);
         merged.images.extend(doc.images);
         merged.networks.extend(doc.networks);
         merged.volumes.extend(doc.volumes);
+        merged.secrets.extend(doc.secrets);
+        merged.pods.extend(doc.pods);
+        merged.jobs.extend(doc.jobs);
+        merged.schedules.extend(doc.schedules);
+        merged.hooks.extend(doc.hooks);
     }
 
     Ok(merged)
@@ -15,6 +15,16 @@ pub struct ParsedDocument {
     pub networks: Vec<ParsedNetwork>,
     #[knuffel(children(name = "volume"))]
     pub volumes: Vec<ParsedVolume>,
+    #[knuffel(children(name = "secret"))]
+    pub secrets: Vec<ParsedSecret>,
+    #[knuffel(children(name = "pod"))]
+    pub pods: Vec<ParsedPod>,
+    #[knuffel(children(name = "job"))]
+    pub jobs: Vec<ParsedJob>,
+    #[knuffel(children(name = "schedule"))]
+    pub schedules: Vec<ParsedSchedule>,
+    #[knuffel(children(name = "hook"))]
+    pub hooks: Vec<ParsedHook>,
 }
 
 #[derive(knuffel::Decode, Debug)]
@@ -26,6 +36,17 @@ pub struct ParsedImage {
     pub reference: Spanned<String, ParseSpan>,
     #[knuffel(property, default)]
     pub local: bool,
+    #[knuffel(child, unwrap(argument))]
+    pub pull: Option<Spanned<ParsedPullPolicy, ParseSpan>>,
+}
+
+#[derive(knuffel::DecodeScalar, Default, Debug, Clone, Copy, PartialEq, Eq)]
+#[knuffel(span_type = LineSpan)]
+pub enum ParsedPullPolicy {
+    #[default]
+    Missing,
+    Always,
+    Newer,
 }
 
 #[derive(knuffel::Decode, Debug)]
@@ -37,6 +58,12 @@ pub struct ParsedContainer {
     pub image: Spanned<String, ParseSpan>,
     #[knuffel(child, unwrap(argument))]
     pub command: Option<Spanned<String, ParseSpan>>,
+    #[knuffel(child, unwrap(argument))]
+    pub pod: Option<Spanned<String, ParseSpan>>,
+    #[knuffel(child, unwrap(argument), default)]
+    pub update_strategy: ParsedUpdateStrategy,
+    #[knuffel(child, unwrap(argument))]
+    pub replicas: Option<Spanned<u32, ParseSpan>>,
     #[knuffel(children(name = "port"))]
     pub ports: Vec<ParsedContainerPort>,
     #[knuffel(children(name = "inject"))]
@@ -47,8 +74,16 @@ pub struct ParsedContainer {
     pub mounts: Vec<ParsedContainerMount>,
     #[knuffel(children(name = "secret"))]
     pub secrets: Vec<ParsedContainerSecret>,
-    #[knuffel(child, unwrap(arguments))]
-    pub after: Vec<String>, // TODO: implement after
+    #[knuffel(child, unwrap(arguments), default)]
+    pub after: Vec<Spanned<String, ParseSpan>>,
+}
+
+#[derive(knuffel::DecodeScalar, Default, Debug, Clone, Copy, PartialEq, Eq)]
+#[knuffel(span_type = LineSpan)]
+pub enum ParsedUpdateStrategy {
+    #[default]
+    StopFirst,
+    StartFirst,
 }
 
 #[derive(Debug)]
@@ -156,8 +191,24 @@ pub struct ParsedContainerBindMount {
 pub struct ParsedContainerSecret {
     #[knuffel(argument)]
     pub name: Spanned<String, ParseSpan>,
+    #[knuffel(property(name = "type"), default)]
+    pub kind: ParsedSecretType,
     #[knuffel(property)]
     pub target: Option<String>,
+    #[knuffel(property)]
+    pub mode: Option<Spanned<String, ParseSpan>>,
+    #[knuffel(property)]
+    pub uid: Option<u32>,
+    #[knuffel(property)]
+    pub gid: Option<u32>,
+}
+
+#[derive(knuffel::DecodeScalar, Default, Debug, Clone, Copy, PartialEq, Eq)]
+#[knuffel(span_type = LineSpan)]
+pub enum ParsedSecretType {
+    #[default]
+    Env,
+    Mount,
 }
 
 #[derive(knuffel::Decode, Debug)]
@@ -181,3 +232,105 @@ pub struct ParsedVolume {
     #[knuffel(child, unwrap(property), default = "local".into())]
     pub driver: String,
 }
+
+#[derive(knuffel::Decode, Debug)]
+#[knuffel(span_type = LineSpan)]
+pub struct ParsedSecret {
+    #[knuffel(argument)]
+    pub name: Spanned<String, ParseSpan>,
+    #[knuffel(child, unwrap(argument))]
+    pub from_file: Option<PathBuf>,
+    #[knuffel(child, unwrap(argument))]
+    pub from_env: Option<String>,
+    #[knuffel(child, unwrap(argument))]
+    pub from_command: Option<Spanned<String, ParseSpan>>,
+    #[knuffel(child, unwrap(argument))]
+    pub from_age: Option<PathBuf>,
+}
+
+#[derive(knuffel::Decode, Debug)]
+#[knuffel(span_type = LineSpan)]
+pub struct ParsedPod {
+    #[knuffel(argument)]
+    pub name: Spanned<String, ParseSpan>,
+    #[knuffel(children(name = "port"))]
+    pub ports: Vec<ParsedContainerPort>,
+    #[knuffel(children(name = "network"))]
+    pub networks: Vec<ParsedContainerNetwork>,
+}
+
+#[derive(knuffel::Decode, Debug)]
+#[knuffel(span_type = LineSpan)]
+pub struct ParsedJob {
+    #[knuffel(argument)]
+    pub name: Spanned<String, ParseSpan>,
+    #[knuffel(child, unwrap(argument))]
+    pub image: Spanned<String, ParseSpan>,
+    #[knuffel(child, unwrap(argument))]
+    pub command: Option<Spanned<String, ParseSpan>>,
+    #[knuffel(children(name = "env"))]
+    pub env: Vec<ParsedEnv>,
+    #[knuffel(children(name = "inject"))]
+    pub injects: Vec<ParsedContainerInject>,
+    #[knuffel(children(name = "network"))]
+    pub networks: Vec<ParsedContainerNetwork>,
+    #[knuffel(children(name = "mount"))]
+    pub mounts: Vec<ParsedContainerMount>,
+    #[knuffel(children(name = "secret"))]
+    pub secrets: Vec<ParsedContainerSecret>,
+    #[knuffel(child, unwrap(arguments), default)]
+    pub after: Vec<Spanned<String, ParseSpan>>,
+}
+
+#[derive(knuffel::Decode, Debug)]
+#[knuffel(span_type = LineSpan)]
+pub struct ParsedSchedule {
+    #[knuffel(argument)]
+    pub name: Spanned<String, ParseSpan>,
+    #[knuffel(property)]
+    pub cron: Spanned<String, ParseSpan>,
+    #[knuffel(child, unwrap(argument))]
+    pub image: Spanned<String, ParseSpan>,
+    #[knuffel(child, unwrap(argument))]
+    pub command: Option<Spanned<String, ParseSpan>>,
+    #[knuffel(children(name = "env"))]
+    pub env: Vec<ParsedEnv>,
+    #[knuffel(children(name = "inject"))]
+    pub injects: Vec<ParsedContainerInject>,
+    #[knuffel(children(name = "network"))]
+    pub networks: Vec<ParsedContainerNetwork>,
+    #[knuffel(children(name = "mount"))]
+    pub mounts: Vec<ParsedContainerMount>,
+    #[knuffel(children(name = "secret"))]
+    pub secrets: Vec<ParsedContainerSecret>,
+}
+
+#[derive(knuffel::Decode, Debug, Clone)]
+#[knuffel(span_type = LineSpan)]
+pub struct ParsedEnv {
+    #[knuffel(argument)]
+    pub name: String,
+    #[knuffel(argument)]
+    pub value: String,
+}
+
+#[derive(knuffel::Decode, Debug)]
+#[knuffel(span_type = LineSpan)]
+pub struct ParsedHook {
+    #[knuffel(argument)]
+    pub kind: Spanned<ParsedHookKind, ParseSpan>,
+    #[knuffel(property)]
+    pub command: Option<Spanned<String, ParseSpan>>,
+    #[knuffel(property)]
+    pub container: Option<Spanned<String, ParseSpan>>,
+    #[knuffel(property)]
+    pub exec: Option<Spanned<String, ParseSpan>>,
+}
+
+#[derive(knuffel::DecodeScalar, Debug, Clone, Copy, PartialEq, Eq)]
+#[knuffel(span_type = LineSpan)]
+pub enum ParsedHookKind {
+    PreSync,
+    PostSync,
+    PostCreate,
+}
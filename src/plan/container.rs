@@ -3,7 +3,7 @@ use std::{
     ops::Deref,
     path::{Path, PathBuf},
     str::FromStr,
-    time::SystemTime,
+    time::{Duration, Instant, SystemTime},
 };
 
 use async_compat::CompatExt;
@@ -12,32 +12,38 @@ use base64::{prelude::BASE64_URL_SAFE_NO_PAD, Engine};
 use maplit::hashmap;
 use miette::Context;
 use podman_api::{
-    models::{ContainerMount, InspectAdditionalNetwork, InspectMount, ListContainer, NamedVolume, Namespace, PortMapping},
-    opts::{ContainerCreateOpts, ContainerListFilter, ContainerListOpts},
+    models::{
+        ContainerMount, InspectAdditionalNetwork, InspectMount, ListContainer, NamedVolume, Namespace, PortMapping, Secret,
+    },
+    opts::{ContainerCreateOpts, ContainerCreateOptsBuilder, ContainerListFilter, ContainerListOpts},
     Podman,
 };
 use serde::{Deserialize, Serialize};
 
 use super::{
-    image::ResolvedImageRef, network::ResolvedNetworkRef, secret::ResolvedSecretRef, volume::ResolvedVolumeRef, PostAction,
-    StepContext,
+    image::ResolvedImageRef, network::ResolvedNetworkRef, pod::ResolvedPodRef, secret::ResolvedSecretRef,
+    volume::ResolvedVolumeRef, Outcome, PostAction, Retry, StepContext,
 };
 use crate::{
-    parse::model::{ParsedContainerInject, ParsedProtocol},
+    parse::model::{ParsedContainerInject, ParsedProtocol, ParsedUpdateStrategy},
     utils::{BodyWriter, IntoDiagnosticShorthand, XTug},
 };
 
 #[derive(Clone, Debug)]
 pub struct ContainerAction {
     pub name: String,
+    pub replica: u32,
     pub command: Option<Vec<String>>,
     pub image: ResolvedImageRef,
+    pub pod: Option<ResolvedPodRef>,
+    pub update_strategy: ParsedUpdateStrategy,
     pub ports: Vec<ContainerActionPort>,
     pub injects: Vec<ParsedContainerInject>,
     pub networks: Vec<ContainerActionNetwork>,
     pub volumes: Vec<ContainerActionVolumeMount>,
     pub secrets: Vec<ContainerActionSecret>,
     pub binds: Vec<ContainerActionBindMount>,
+    pub post_create: Vec<Vec<String>>,
 }
 
 #[derive(Clone, Debug)]
@@ -69,39 +75,59 @@ pub struct ContainerActionBindMount {
 pub struct ContainerActionSecret {
     pub name_ref: ResolvedSecretRef,
     pub target: String,
+    pub kind: ContainerActionSecretKind,
+}
+
+#[derive(Clone, PartialEq, Eq, PartialOrd, Ord, Debug, Deserialize, Serialize)]
+pub enum ContainerActionSecretKind {
+    #[serde(rename = "e")]
+    Env,
+    #[serde(rename = "m")]
+    Mount {
+        #[serde(rename = "m")]
+        mode: Option<u32>,
+        #[serde(rename = "u")]
+        uid: Option<u32>,
+        #[serde(rename = "g")]
+        gid: Option<u32>,
+    },
 }
 
-pub async fn execute(ctx: &StepContext, action: ContainerAction) -> miette::Result<()> {
+pub async fn execute(ctx: &StepContext, action: ContainerAction) -> miette::Result<Outcome> {
     let mut fingerprint_cache = HashMap::new();
     let mut secret_fulls = None;
 
-    let remote_containers = remote_containers_query(&ctx.service, ctx.group.clone(), action.name.clone()).await?;
+    let remote_containers = remote_containers_query(&ctx.service, ctx.group.clone(), action.name.clone())
+        .await?
+        .into_iter()
+        .filter(|container| replica_index(container) == action.replica)
+        .collect::<Vec<_>>();
 
     if remote_containers.is_empty() {
-        create_container(ctx, &action, fingerprint_cache, secret_fulls)
+        let id = create_container(ctx, &action, fingerprint_cache, secret_fulls)
             .await
             .wrap_err("creating container")?;
-        return Ok(());
+        post_create(ctx, &action, &id).await?;
+        return Ok(Outcome::Created);
     }
 
     let first_container = remote_containers.first().unwrap();
-    let first_container_inspect = ctx
-        .service
-        .containers()
-        .get(first_container.id.as_ref().unwrap())
-        .inspect()
-        .await
-        .d()?;
+    let first_container_handle = ctx.service.containers().get(first_container.id.as_ref().unwrap());
+    let first_container_inspect = Retry::QUERY.run(|| first_container_handle.inspect()).await.d()?;
+
+    let expected_pod = action.pod.map(|pod| ctx.resolved_pods.lock()[&pod].clone());
 
     if remote_containers.len() == 1
         && first_container_inspect.image == Some(ctx.resolved_images.lock()[&action.image].to_string())
         && first_container.command == action.command
-        && check_port_mappings(&action.ports, first_container.ports.as_deref().unwrap_or_default())
-        && check_network_mappings(
-            ctx,
-            &action.networks,
-            &first_container_inspect.network_settings.unwrap().networks.unwrap_or_default(),
-        )
+        && first_container.pod.as_deref().filter(|pod| !pod.is_empty()) == expected_pod.as_deref()
+        && (expected_pod.is_some() || check_port_mappings(&action.ports, first_container.ports.as_deref().unwrap_or_default()))
+        && (expected_pod.is_some()
+            || check_network_mappings(
+                ctx,
+                &action.networks,
+                &first_container_inspect.network_settings.unwrap().networks.unwrap_or_default(),
+            ))
         && check_mount_mappings(
             ctx,
             &action.volumes,
@@ -171,10 +197,28 @@ pub async fn execute(ctx: &StepContext, action: ContainerAction) -> miette::Resu
                     .d()
                     .wrap_err_with(|| format!("starting container {id}"))?;
             }
-            return Ok(());
+            return Ok(Outcome::Unchanged);
         }
     }
 
+    // pod members can't be started alongside the old container as they share the
+    // pod's ports, and neither can anything that binds a host port the old one
+    // is still holding
+    let start_first = action.update_strategy == ParsedUpdateStrategy::StartFirst
+        && action.pod.is_none()
+        && !ports_collide(&action.ports, &remote_containers);
+
+    let mut fingerprint_cache = Some(fingerprint_cache);
+    if start_first {
+        let id = create_container(ctx, &action, fingerprint_cache.take().unwrap(), secret_fulls.take())
+            .await
+            .wrap_err("creating container")?;
+        wait_ready(ctx, &id)
+            .await
+            .wrap_err_with(|| format!("waiting for container {id} to become ready"))?;
+        post_create(ctx, &action, &id).await?;
+    }
+
     for container in remote_containers {
         let id = container.id.unwrap();
         let container = ctx.service.containers().get(&id);
@@ -183,26 +227,90 @@ pub async fn execute(ctx: &StepContext, action: ContainerAction) -> miette::Resu
         ctx.finalize.lock().push(PostAction::DeleteContainer { id });
     }
 
-    create_container(ctx, &action, fingerprint_cache, secret_fulls)
-        .await
-        .wrap_err("creating container")?;
+    if let Some(fingerprint_cache) = fingerprint_cache {
+        let id = create_container(ctx, &action, fingerprint_cache, secret_fulls)
+            .await
+            .wrap_err("creating container")?;
+        post_create(ctx, &action, &id).await?;
+    }
+
+    Ok(Outcome::Recreated)
+}
+
+async fn post_create(ctx: &StepContext, action: &ContainerAction, id: &str) -> miette::Result<()> {
+    for command in &action.post_create {
+        super::hook::run_exec(ctx, &action.name, id, command)
+            .await
+            .wrap_err("running post-create hook")?;
+    }
 
     Ok(())
 }
 
+fn ports_collide(expected: &[ContainerActionPort], remote_containers: &[ListContainer]) -> bool {
+    remote_containers
+        .iter()
+        .flat_map(|container| container.ports.as_deref().unwrap_or_default())
+        .any(|mapping| {
+            expected
+                .iter()
+                .any(|port| mapping.host_port == Some(port.host) && mapping.protocol.as_deref() == Some(port.protocol.as_str()))
+        })
+}
+
+const READY_TIMEOUT: Duration = Duration::from_secs(120);
+const READY_POLL_INTERVAL: Duration = Duration::from_millis(500);
+
+// waits for the container to be running, and healthy if it has a health check
+async fn wait_ready(ctx: &StepContext, id: &str) -> miette::Result<()> {
+    let container = ctx.service.containers().get(id);
+    let deadline = Instant::now() + READY_TIMEOUT;
+
+    loop {
+        let Some(state) = Retry::QUERY.run(|| container.inspect()).await.d()?.state else {
+            return Err(miette::miette!("container has no state"));
+        };
+        let health = state.health.and_then(|health| health.status).unwrap_or_default();
+        match (state.status.as_deref(), health.as_str()) {
+            (Some("running"), "" | "healthy") => return Ok(()),
+            (Some("running"), "unhealthy") => return Err(miette::miette!("container is unhealthy")),
+            (Some("running" | "created" | "initialized"), _) => {}
+            (status, _) => {
+                return Err(miette::miette!(
+                    "container is {} with exit code {}",
+                    status.unwrap_or("unknown"),
+                    state.exit_code.unwrap_or_default()
+                ))
+            }
+        }
+
+        if Instant::now() >= deadline {
+            return Err(miette::miette!("timed out after {}s", READY_TIMEOUT.as_secs()));
+        }
+        tokio::time::sleep(READY_POLL_INTERVAL).await;
+    }
+}
+
 pub async fn remote_containers_query(service: &Podman, group: String, name: String) -> miette::Result<Vec<ListContainer>> {
-    service
-        .containers()
-        .list(
-            &ContainerListOpts::builder()
-                .filter([
-                    ContainerListFilter::LabelKeyVal(XTug::Group.to_string(), group),
-                    ContainerListFilter::LabelKeyVal(XTug::Name.to_string(), name),
-                ])
-                .build(),
-        )
-        .await
-        .d()
+    let opts = ContainerListOpts::builder()
+        .filter([
+            ContainerListFilter::LabelKeyVal(XTug::Group.to_string(), group),
+            ContainerListFilter::LabelKeyVal(XTug::Name.to_string(), name),
+        ])
+        .build();
+    let containers = service.containers();
+    Retry::QUERY.run(|| containers.list(&opts)).await.d()
+}
+
+// containers from before replicas existed don't carry an index, and are
+// treated as the first replica
+pub fn replica_index(container: &ListContainer) -> u32 {
+    container
+        .labels
+        .as_ref()
+        .and_then(|labels| labels.get(XTug::Replica.as_ref()))
+        .and_then(|replica| replica.parse().ok())
+        .unwrap_or(0)
 }
 
 async fn create_container(
@@ -210,7 +318,7 @@ async fn create_container(
     action: &ContainerAction,
     fingerprint_cache: HashMap<PathBuf, InjectNode>,
     secret_fulls: Option<Vec<FullSecret>>,
-) -> miette::Result<()> {
+) -> miette::Result<String> {
     let image = ctx.resolved_images.lock()[&action.image].to_string();
     let mut inject_fingerprints = fingerprint_cache;
     for inject in &action.injects {
@@ -226,38 +334,19 @@ async fn create_container(
     }
     let inject_fingerprints = BASE64_URL_SAFE_NO_PAD.encode(rmp_serde::to_vec(&inject_fingerprints).d()?);
 
-    let mut opts = ContainerCreateOpts::builder()
-        .image(image)
-        .portmappings(action.ports.iter().map(|port| PortMapping {
-            container_port: Some(port.container),
-            host_ip: None,
-            host_port: Some(port.host),
-            protocol: Some(port.protocol.to_string()),
-            range: None,
-        }))
-        .net_namespace(Namespace {
-            nsmode: Some("bridge".to_string()),
-            value: None,
-        })
-        .networks(action.networks.iter().map(|network| {
-            (ctx.resolved_networks.lock()[&network.resolved].to_string(), hashmap! {
-                "aliases" => network.aliases.clone()
-            })
-        }))
-        .volumes(action.volumes.iter().map(|volume| NamedVolume {
-            dest: Some(volume.destination.clone()),
-            is_anonymous: Some(false),
-            name: Some(ctx.resolved_volumes.lock()[&volume.name_ref].clone()),
-            options: None,
-        }))
-        .mounts(action.binds.iter().map(|bind| ContainerMount {
-            destination: Some(bind.destination.clone()),
-            options: None,
-            source: Some(bind.source.to_string_lossy().to_string()),
-            _type: Some("bind".to_string()),
-            uid_mappings: None,
-            gid_mappings: None,
-        }));
+    let mut opts = storage_opts(
+        ctx,
+        ContainerCreateOpts::builder().image(image),
+        &action.volumes,
+        &action.binds,
+    );
+
+    // pod members share the pod's network namespace, so ports and networks live on
+    // the pod
+    opts = match action.pod {
+        Some(pod) => opts.pod(ctx.resolved_pods.lock()[&pod].clone()),
+        None => network_opts(ctx, opts, &action.networks, &action.ports),
+    };
 
     if let Some(command) = &action.command {
         opts = opts.command(command);
@@ -268,16 +357,13 @@ async fn create_container(
         None => secret_fingerprint(ctx, &action.secrets, None).await?.0,
     };
 
-    opts = opts.secret_env(
-        secret_fulls
-            .iter()
-            .map(|secret| (secret.target.to_string(), secret.id.clone())),
-    );
+    opts = secret_opts(opts, &secret_fulls);
     let print = BASE64_URL_SAFE_NO_PAD.encode(rmp_serde::to_vec(&secret_print_from_fulls(&secret_fulls)).d()?);
 
     opts = opts.labels([
         (XTug::Group.to_string(), ctx.group.clone()),
         (XTug::Name.to_string(), action.name.to_string()),
+        (XTug::Replica.to_string(), action.replica.to_string()),
         (XTug::InjectFingerprint.to_string(), inject_fingerprints),
         (XTug::SecretFingerprint.to_string(), print),
     ]);
@@ -285,12 +371,89 @@ async fn create_container(
     let new_container = ctx.service.containers().create(&opts.build()).await.d()?;
     let container = ctx.service.containers().get(&new_container.id);
 
+    inject_files(ctx, &new_container.id, &action.injects).await?;
+
+    container.start(None).await.d()?;
+
+    ctx.backtrack.lock().push(PostAction::DeleteContainer {
+        id: container.id().to_string(),
+    });
+
+    Ok(new_container.id)
+}
+
+pub fn storage_opts(
+    ctx: &StepContext,
+    opts: ContainerCreateOptsBuilder,
+    volumes: &[ContainerActionVolumeMount],
+    binds: &[ContainerActionBindMount],
+) -> ContainerCreateOptsBuilder {
+    opts.volumes(volumes.iter().map(|volume| NamedVolume {
+        dest: Some(volume.destination.clone()),
+        is_anonymous: Some(false),
+        name: Some(ctx.resolved_volumes.lock()[&volume.name_ref].clone()),
+        options: None,
+    }))
+    .mounts(binds.iter().map(|bind| ContainerMount {
+        destination: Some(bind.destination.clone()),
+        options: None,
+        source: Some(bind.source.to_string_lossy().to_string()),
+        _type: Some("bind".to_string()),
+        uid_mappings: None,
+        gid_mappings: None,
+    }))
+}
+
+pub fn network_opts(
+    ctx: &StepContext,
+    opts: ContainerCreateOptsBuilder,
+    networks: &[ContainerActionNetwork],
+    ports: &[ContainerActionPort],
+) -> ContainerCreateOptsBuilder {
+    opts.portmappings(ports.iter().map(|port| PortMapping {
+        container_port: Some(port.container),
+        host_ip: None,
+        host_port: Some(port.host),
+        protocol: Some(port.protocol.to_string()),
+        range: None,
+    }))
+    .net_namespace(Namespace {
+        nsmode: Some("bridge".to_string()),
+        value: None,
+    })
+    .networks(networks.iter().map(|network| {
+        (ctx.resolved_networks.lock()[&network.resolved].to_string(), hashmap! {
+            "aliases" => network.aliases.clone()
+        })
+    }))
+}
+
+pub fn secret_opts(opts: ContainerCreateOptsBuilder, secret_fulls: &[FullSecret]) -> ContainerCreateOptsBuilder {
+    opts.secret_env(
+        secret_fulls
+            .iter()
+            .filter(|secret| secret.kind == ContainerActionSecretKind::Env)
+            .map(|secret| (secret.target.to_string(), secret.id.clone())),
+    )
+    .secrets(secret_fulls.iter().filter_map(|secret| match secret.kind {
+        ContainerActionSecretKind::Env => None,
+        ContainerActionSecretKind::Mount { mode, uid, gid } => Some(Secret {
+            source: Some(secret.id.clone()),
+            target: Some(secret.target.clone()),
+            mode,
+            uid,
+            gid,
+        }),
+    }))
+}
+
+pub async fn inject_files(ctx: &StepContext, id: &str, injects: &[ParsedContainerInject]) -> miette::Result<()> {
     let cwd = std::env::current_dir().d()?;
-    for inject in &action.injects {
+    for inject in injects {
         let (writer, body) = BodyWriter::new();
         let copy_task = tokio::spawn({
             let at = inject.at.deref().clone();
-            let container = ctx.service.containers().get(&new_container.id);
+            let container = ctx.service.containers().get(id);
             async move { container.copy_to(at, body).await }
         });
         let mut archive = async_tar::Builder::new(writer);
@@ -300,12 +463,6 @@ async fn create_container(
         copy_task.await.d()?.d()?;
     }
 
-    container.start(None).await.d()?;
-
-    ctx.backtrack.lock().push(PostAction::DeleteContainer {
-        id: container.id().to_string(),
-    });
-
     Ok(())
 }
 
@@ -399,7 +556,7 @@ fn check_mount_mappings(
     for definition in expected_volumes {
         let volume = ctx.resolved_volumes.lock()[&definition.name_ref].clone();
         match actual.iter().enumerate().find(|(_, mount)| {
-            mount.type_.as_ref().map(|x| x.as_str()) == Some("volume")
+            mount.type_.as_deref() == Some("volume")
                 && mount.name.as_ref() == Some(&volume)
                 && mount.destination.as_ref() == Some(&definition.destination)
         }) {
@@ -412,9 +569,9 @@ fn check_mount_mappings(
 
     for definition in expected_binds {
         match actual.iter().enumerate().find(|(_, mount)| {
-            mount.type_.as_ref().map(|x| x.as_str()) == Some("bind")
+            mount.type_.as_deref() == Some("bind")
                 && mount.destination.as_ref() == Some(&definition.destination)
-                && mount.source.as_ref().and_then(|x| PathBuf::from_str(&x).ok()).as_ref() == Some(&definition.source)
+                && mount.source.as_ref().and_then(|x| PathBuf::from_str(x).ok()).as_ref() == Some(&definition.source)
         }) {
             Some((index, _)) => {
                 actual.swap_remove(index);
@@ -426,7 +583,7 @@ fn check_mount_mappings(
     actual.is_empty()
 }
 
-async fn secret_fingerprint(
+pub async fn secret_fingerprint(
     ctx: &StepContext,
     secrets: &[ContainerActionSecret],
     compare: Option<&[SecretFingerprint]>,
@@ -434,12 +591,24 @@ async fn secret_fingerprint(
     let mut fulls = Vec::new();
     for secret in secrets {
         let secret_id = ctx.resolved_secrets.lock()[&secret.name_ref].clone();
-        let info = ctx.service.secrets().get(&secret_id).inspect().await.d()?;
-        let updated_at = info.updated_at.unwrap().timestamp();
+        let remote_secret = ctx.service.secrets().get(&secret_id);
+        let info = Retry::QUERY.run(|| remote_secret.inspect()).await.d()?;
+        let version = match info
+            .spec
+            .and_then(|spec| spec.labels)
+            .and_then(|mut labels| labels.remove(XTug::SecretDigest.as_ref()))
+        {
+            Some(digest) => SecretVersion::Digest(digest),
+            None => SecretVersion::UpdatedAt {
+                id: secret_id.clone(),
+                updated_at: info.updated_at.unwrap().timestamp(),
+            },
+        };
         fulls.push(FullSecret {
             id: secret_id,
             target: secret.target.clone(),
-            updated_at,
+            kind: secret.kind.clone(),
+            version,
         });
     }
 
@@ -451,12 +620,13 @@ async fn secret_fingerprint(
     }
 }
 
-fn secret_print_from_fulls(fulls: &[FullSecret]) -> Vec<SecretFingerprint> {
+pub fn secret_print_from_fulls(fulls: &[FullSecret]) -> Vec<SecretFingerprint> {
     let mut prints = Vec::new();
     for full in fulls {
         prints.push(SecretFingerprint {
-            id: full.id.clone(),
-            updated_at: full.updated_at,
+            target: full.target.clone(),
+            kind: full.kind.clone(),
+            version: full.version.clone(),
         });
     }
     prints.sort_unstable();
@@ -464,18 +634,30 @@ fn secret_print_from_fulls(fulls: &[FullSecret]) -> Vec<SecretFingerprint> {
 }
 
 #[derive(PartialEq, Eq, PartialOrd, Ord, Deserialize, Serialize)]
-struct SecretFingerprint {
-    id: String,
-    updated_at: i64,
+pub struct SecretFingerprint {
+    target: String,
+    kind: ContainerActionSecretKind,
+    version: SecretVersion,
+}
+
+// secrets created by tug carry a digest of their content, anything else only
+// has a timestamp to go by
+#[derive(PartialEq, Eq, PartialOrd, Ord, Deserialize, Serialize, Clone)]
+pub enum SecretVersion {
+    #[serde(rename = "d")]
+    Digest(String),
+    #[serde(rename = "u")]
+    UpdatedAt { id: String, updated_at: i64 },
 }
 
-struct FullSecret {
+pub struct FullSecret {
     id: String,
     target: String,
-    updated_at: i64,
+    kind: ContainerActionSecretKind,
+    version: SecretVersion,
 }
 
-async fn inject_fingerprint(
+pub async fn inject_fingerprint(
     ctx: &StepContext,
     inject: &ParsedContainerInject,
     compare: Option<&InjectNode>,
@@ -526,8 +708,8 @@ async fn compute_node(at: &Path, compare: &Option<&InjectNode>) -> miette::Resul
     }
 }
 
-#[derive(Serialize, Deserialize, Clone)]
-enum InjectNode {
+#[derive(PartialEq, Serialize, Deserialize, Clone)]
+pub enum InjectNode {
     #[serde(rename = "d")]
     Directory(HashMap<Vec<u8>, InjectNode>),
     #[serde(rename = "f")]
@@ -1,34 +1,35 @@
-use podman_api::opts::{ContainerListFilter, ContainerListOpts};
+use std::collections::HashMap;
 
-use super::{PostAction, StepContext};
+use podman_api::{
+    models::ListContainer,
+    opts::{ContainerListFilter, ContainerListOpts, PodListFilter, PodListOpts},
+};
+
+use super::{Outcome, PostAction, Retry, StepContext};
 use crate::utils::{IntoDiagnosticShorthand, XTug};
 
 #[derive(Clone, Debug)]
 pub struct GarbageAction {
-    pub container_names: Vec<String>,
+    pub container_replicas: HashMap<String, u32>,
+    pub secret_names: Vec<String>,
+    pub pod_names: Vec<String>,
+    pub job_names: Vec<String>,
+    pub schedule_names: Vec<String>,
 }
 
-pub async fn execute(ctx: &StepContext, action: GarbageAction) -> miette::Result<()> {
-    let remote_containers = ctx
-        .service
-        .containers()
-        .list(
-            &ContainerListOpts::builder()
-                .all(true)
-                .filter([
-                    ContainerListFilter::LabelKeyVal(XTug::Group.to_string(), ctx.group.clone()),
-                    ContainerListFilter::LabelKey(XTug::Name.to_string()),
-                ])
-                .build(),
-        )
-        .await
-        .d()?;
+pub async fn execute(ctx: &StepContext, action: GarbageAction) -> miette::Result<Outcome> {
+    let remote_containers = remote_labelled_containers(ctx, XTug::Name).await?;
 
     let mut to_stop = Vec::new();
 
     for container in remote_containers {
+        let replica = super::container::replica_index(&container);
         if let (Some(id), Some(name)) = (container.id, container.labels.unwrap_or_default().remove(XTug::Name.as_ref())) {
-            if !action.container_names.contains(&name) {
+            if action
+                .container_replicas
+                .get(&name)
+                .map_or(true, |replicas| replica >= *replicas)
+            {
                 if container.status.as_deref() == Some("running") {
                     to_stop.push(id.clone());
                     ctx.backtrack.lock().push(PostAction::RestartContainer { id: id.clone() });
@@ -38,6 +39,70 @@ pub async fn execute(ctx: &StepContext, action: GarbageAction) -> miette::Result
         }
     }
 
+    let remote_jobs = remote_labelled_containers(ctx, XTug::Job).await?;
+
+    for job in remote_jobs {
+        if let (Some(id), Some(name)) = (job.id, job.labels.unwrap_or_default().remove(XTug::Job.as_ref())) {
+            if !action.job_names.contains(&name) {
+                if job.state.as_deref() == Some("running") {
+                    to_stop.push(id.clone());
+                }
+                ctx.finalize.lock().push(PostAction::DeleteContainer { id });
+            }
+        }
+    }
+
+    let remote_schedules = remote_labelled_containers(ctx, XTug::Schedule).await?;
+
+    for schedule in remote_schedules {
+        if let (Some(id), Some(name)) = (
+            schedule.id,
+            schedule.labels.unwrap_or_default().remove(XTug::Schedule.as_ref()),
+        ) {
+            if !action.schedule_names.contains(&name) {
+                ctx.finalize.lock().push(PostAction::DeleteContainer { id });
+            }
+        }
+    }
+
+    if ctx.raw_service.uri().starts_with("unix://") {
+        for (unit, name) in super::schedule::local_schedule_units(&ctx.group).await? {
+            if !action.schedule_names.contains(&name) {
+                ctx.finalize.lock().push(PostAction::DeleteSchedule { unit });
+            }
+        }
+    }
+
+    let opts = PodListOpts::builder()
+        .filter([
+            PodListFilter::LabelKeyVal(XTug::Group.to_string(), ctx.group.clone()),
+            PodListFilter::LabelKey(XTug::Name.to_string()),
+        ])
+        .build();
+    let pods = ctx.service.pods();
+    let remote_pods = Retry::QUERY.run(|| pods.list(&opts)).await.d()?;
+
+    for pod in remote_pods {
+        if let (Some(id), Some(name)) = (pod.id, pod.labels.unwrap_or_default().remove(XTug::Name.as_ref())) {
+            if !action.pod_names.contains(&name) {
+                ctx.finalize.lock().push(PostAction::DeletePod { id });
+            }
+        }
+    }
+
+    let secrets = ctx.service.secrets();
+    for secret in Retry::QUERY.run(|| secrets.list()).await.d()? {
+        let labels = secret.spec.and_then(|spec| spec.labels).unwrap_or_default();
+        if labels.get(XTug::Group.as_ref()) != Some(&ctx.group) {
+            continue;
+        }
+        if let (Some(id), Some(name)) = (secret.id, labels.get(XTug::Name.as_ref())) {
+            if !action.secret_names.contains(name) {
+                ctx.finalize.lock().push(PostAction::DeleteSecret { id });
+            }
+        }
+    }
+
     futures_util::future::try_join_all(to_stop.into_iter().map(|id| {
         tokio::spawn({
             let service = ctx.service.clone();
@@ -48,5 +113,22 @@ pub async fn execute(ctx: &StepContext, action: GarbageAction) -> miette::Result
     .await
     .d()?;
 
-    Ok(())
+    Ok(if ctx.finalize.lock().is_empty() {
+        Outcome::Unchanged
+    } else {
+        Outcome::Deleted
+    })
+}
+
+// every container in the group carrying `kind`, whatever it's named
+async fn remote_labelled_containers(ctx: &StepContext, kind: XTug) -> miette::Result<Vec<ListContainer>> {
+    let opts = ContainerListOpts::builder()
+        .all(true)
+        .filter([
+            ContainerListFilter::LabelKeyVal(XTug::Group.to_string(), ctx.group.clone()),
+            ContainerListFilter::LabelKey(kind.to_string()),
+        ])
+        .build();
+    let containers = ctx.service.containers();
+    Retry::QUERY.run(|| containers.list(&opts)).await.d()
 }
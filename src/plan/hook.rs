@@ -0,0 +1,53 @@
+use std::path::Path;
+
+use miette::Context;
+use podman_api::opts::{ExecCreateOpts, ExecStartOpts};
+
+use super::{job::log_output, StepContext};
+use crate::{logger::Logger, utils::IntoDiagnosticShorthand};
+
+pub async fn run_local(logger: &Logger, command: &[String], root_directory: &Path) -> miette::Result<()> {
+    logger.info(format!("Running `{}`", command.join(" ")));
+    let status = tokio::process::Command::new(&command[0])
+        .args(&command[1..])
+        .current_dir(root_directory)
+        .stdin(std::process::Stdio::null())
+        .status()
+        .await
+        .d()
+        .wrap_err_with(|| format!("running `{}`", command[0]))?;
+    if !status.success() {
+        return Err(miette::miette!("hook `{}` exited with {status}", command.join(" ")));
+    }
+
+    Ok(())
+}
+
+pub async fn run_exec(ctx: &StepContext, name: &str, id: &str, command: &[String]) -> miette::Result<()> {
+    let exec = ctx
+        .service
+        .containers()
+        .get(id)
+        .create_exec(
+            &ExecCreateOpts::builder()
+                .command(command)
+                .attach_stdout(true)
+                .attach_stderr(true)
+                .build(),
+        )
+        .await
+        .d()?;
+    if let Some(output) = exec.start(&ExecStartOpts::builder().build()).await.d()? {
+        log_output(&ctx.logger, output).await?;
+    }
+
+    let exit_code = exec.inspect().await.d()?["ExitCode"].as_i64().unwrap_or_default();
+    if exit_code != 0 {
+        return Err(miette::miette!(
+            "hook `{}` in container `{name}` exited with code {exit_code}",
+            command.join(" ")
+        ));
+    }
+
+    Ok(())
+}
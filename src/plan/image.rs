@@ -1,12 +1,23 @@
-use futures_util::TryStreamExt;
+use std::{
+    collections::{BTreeMap, HashSet},
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+use futures_util::{StreamExt, TryStreamExt};
 use miette::{NamedSource, SourceSpan};
 use podman_api::{
-    opts::{ImageListFilter, ImageListOpts, PullOpts},
-    Id,
+    opts::{ContainerListFilter, ContainerListOpts, ImageListFilter, ImageListOpts, ImageTagOpts, PullOpts, PullPolicy},
+    Id, Podman,
 };
 
-use super::StepContext;
-use crate::{parse::span::ParseSpan, utils::IntoDiagnosticShorthand};
+use super::{Outcome, Retry, StepContext};
+use crate::{
+    logger::Logger,
+    parse::{model::ParsedPullPolicy, span::ParseSpan},
+    prepare::diagnostics::read_source,
+    registry::{self, Credentials},
+    utils::{IntoDiagnosticShorthand, XTug},
+};
 
 #[derive(Clone, Debug)]
 pub struct ImageAction {
@@ -15,63 +26,417 @@ pub struct ImageAction {
     pub reference: String,
     pub reference_span: ParseSpan,
     pub local: bool,
+    pub pull: ParsedPullPolicy,
+    // the digest from tug.lock, used instead of the reference
+    pub pinned: Option<String>,
+    // set by pre_pull, so the step doesn't pull again
+    pub pre_pulled: Option<PrePulled>,
+}
+
+#[derive(Clone, Debug)]
+pub struct PrePulled {
+    // the image id the reference had before pulling, if any
+    pub before: Option<String>,
+    pub id: String,
 }
 
 #[derive(Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Debug)]
 pub struct ResolvedImageRef(pub usize);
 
-pub async fn execute(ctx: &StepContext, action: ImageAction) -> miette::Result<()> {
-    let image_service = ctx.service.images();
-
-    let (id, tag) = match action.reference.split_once(':') {
-        Some((id, tag)) => (id, Some(tag.to_string())),
-        None => (action.reference.as_str(), None),
+pub async fn execute(ctx: &StepContext, action: ImageAction) -> miette::Result<Outcome> {
+    let (id, outcome) = match &action.pinned {
+        Some(pinned) => resolve_pinned(ctx, &action, pinned).await?,
+        None => resolve(ctx, &action).await?,
     };
+    track(ctx, &action, &id).await?;
+    ctx.resolved_images.lock().insert(action.resolved, id);
 
-    let result = image_service
-        .list(
-            &ImageListOpts::builder()
-                .filter([ImageListFilter::Reference(Id::from(id), tag)])
-                .build(),
-        )
-        .await
-        .d()?;
-
-    if result.len() > 1 {
-        println!(
-            "Warning: two images found for reference {}, choosing the first one",
-            action.reference
-        );
-    }
+    Ok(outcome)
+}
 
-    if let Some(summary) = result.into_iter().next() {
-        ctx.resolved_images.lock().insert(action.resolved, summary.id.unwrap());
-        return Ok(());
+async fn resolve(ctx: &StepContext, action: &ImageAction) -> miette::Result<(String, Outcome)> {
+    let local_id = match &action.pre_pulled {
+        Some(pre_pulled) => pre_pulled.before.clone(),
+        None => local_id(&ctx.service, &ctx.logger, &action.reference).await?,
+    };
+    if let Some(id) = &local_id {
+        if action.local || action.pull == ParsedPullPolicy::Missing {
+            return Ok((id.clone(), Outcome::Unchanged));
+        }
     }
 
     if action.local {
         Err(ImageNotFound {
             name: action.reference.clone(),
-            content: crate::prepare::diagnostics::read_source(&action.reference_span)?,
+            content: read_source(&action.reference_span)?,
             reference: action.reference_span.source_span(),
             help: "images marked as local don't get automatically pulled",
         })?;
     }
 
-    let mut stream = image_service.pull(&PullOpts::builder().reference(action.reference.clone()).build());
+    let id = match &action.pre_pulled {
+        Some(pre_pulled) => pre_pulled.id.clone(),
+        None => {
+            pull_image(
+                &ctx.service,
+                &ctx.credentials,
+                &action.reference,
+                &action.reference_span,
+                pull_policy(action.pull),
+                |line| ctx.progress.set_message(line),
+            )
+            .await?
+        }
+    };
+    let outcome = match &local_id {
+        None => Outcome::Created,
+        Some(local_id) if *local_id == id => Outcome::Unchanged,
+        Some(_) => {
+            ctx.logger.info(format!("Updated to {}", &id[..12.min(id.len())]));
+            Outcome::Updated
+        }
+    };
+
+    Ok((id, outcome))
+}
+
+// the id of the image a reference points to right now
+async fn local_id(service: &Podman, logger: &Logger, reference: &str) -> miette::Result<Option<String>> {
+    let image_service = service.images();
 
-    while let Some(report) = stream.try_next().await.d()? {
-        if let Some(err) = report.error {
-            return Err(miette::miette!(err));
+    let (id, tag) = match reference.split_once(':') {
+        Some((id, tag)) => (id, Some(tag.to_string())),
+        None => (reference, None),
+    };
+
+    let opts = ImageListOpts::builder()
+        .filter([ImageListFilter::Reference(Id::from(id), tag)])
+        .build();
+    let result = Retry::QUERY.run(|| image_service.list(&opts)).await.d()?;
+
+    if result.len() > 1 {
+        logger.warn(format!("two images found for reference {reference}, choosing the first one"));
+    }
+
+    Ok(result.into_iter().next().map(|summary| summary.id.unwrap()))
+}
+
+// a locked digest never moves, so all that matters is whether it's here yet
+async fn resolve_pinned(ctx: &StepContext, action: &ImageAction, pinned: &str) -> miette::Result<(String, Outcome)> {
+    if let Some(pre_pulled) = &action.pre_pulled {
+        let outcome = match pre_pulled.before {
+            Some(_) => Outcome::Unchanged,
+            None => Outcome::Created,
+        };
+        return Ok((pre_pulled.id.clone(), outcome));
+    }
+
+    match pinned_id(&ctx.service, pinned).await? {
+        Some(id) => Ok((id, Outcome::Unchanged)),
+        None => {
+            let id = pull_image(
+                &ctx.service,
+                &ctx.credentials,
+                pinned,
+                &action.reference_span,
+                PullPolicy::Missing,
+                |line| ctx.progress.set_message(line),
+            )
+            .await?;
+            Ok((id, Outcome::Created))
         }
+    }
+}
 
-        if let Some(id) = report.id {
-            ctx.resolved_images.lock().insert(action.resolved, id);
-            return Ok(());
+async fn pinned_id(service: &Podman, pinned: &str) -> miette::Result<Option<String>> {
+    let image = service.images().get(pinned);
+    match Retry::QUERY.run(|| image.inspect()).await {
+        Ok(inspect) => Ok(inspect.id),
+        Err(podman_api::Error::Fault { code, .. }) if code == hyper::StatusCode::NOT_FOUND => Ok(None),
+        Err(err) => Err(err).d(),
+    }
+}
+
+// podman can't label an image after the fact, so tug tags every image it
+// resolves as `localhost/tug-<group>/<name>:<first used>` to know which ones
+// it's responsible for and how old they are
+async fn track(ctx: &StepContext, action: &ImageAction, id: &str) -> miette::Result<()> {
+    let repository = tracking_repository(&ctx.group, &action.name);
+    let image = ctx.service.images().get(id);
+    let inspect = Retry::QUERY.run(|| image.inspect()).await.d()?;
+    let tracked = inspect
+        .repo_tags
+        .unwrap_or_default()
+        .iter()
+        .any(|tag| tag.starts_with(&format!("{repository}:")));
+    if !tracked {
+        let now = SystemTime::now().duration_since(UNIX_EPOCH).unwrap_or_default();
+        image
+            .tag(
+                &ImageTagOpts::builder()
+                    .repo(&repository)
+                    .tag(now.as_secs().to_string())
+                    .build(),
+            )
+            .await
+            .d()?;
+    }
+
+    Ok(())
+}
+
+pub fn tracking_repository(group: &str, name: &str) -> String {
+    // repository names are picky about what they contain
+    let sanitize = |part: &str| {
+        part.to_lowercase()
+            .chars()
+            .map(|c| match c {
+                'a'..='z' | '0'..='9' | '.' | '_' | '-' => c,
+                _ => '-',
+            })
+            .collect::<String>()
+    };
+    format!("localhost/tug-{}/{}", sanitize(group), sanitize(name))
+}
+
+// removes tracked images no container of the group uses anymore, keeping the
+// `keep` most recent of those for every image name
+pub async fn collect_garbage(service: &Podman, group: &str, logger: &Logger, keep: usize) -> miette::Result<()> {
+    let prefix = tracking_repository(group, "");
+    let images = service.images();
+    let containers = service.containers();
+
+    let opts = ContainerListOpts::builder()
+        .all(true)
+        .filter([ContainerListFilter::LabelKeyVal(XTug::Group.to_string(), group.to_string())])
+        .build();
+    let in_use = Retry::QUERY
+        .run(|| containers.list(&opts))
+        .await
+        .d()?
+        .into_iter()
+        .filter_map(|container| container.image_id)
+        .collect::<HashSet<_>>();
+
+    // versions of every tracked image name, as (first used, tag, image id)
+    let opts = ImageListOpts::builder().build();
+    let mut versions = BTreeMap::<String, Vec<(u64, String, String)>>::new();
+    for summary in Retry::QUERY.run(|| images.list(&opts)).await.d()? {
+        let id = summary.id.unwrap_or_default();
+        for tag in summary.repo_tags.unwrap_or_default() {
+            let Some((name, since)) = tag.strip_prefix(&prefix).and_then(|rest| rest.rsplit_once(':')) else {
+                continue;
+            };
+            let Ok(since) = since.parse() else { continue };
+            versions
+                .entry(name.to_string())
+                .or_default()
+                .push((since, tag.clone(), id.clone()));
+        }
+    }
+
+    for (name, versions) in versions {
+        for (tag, id) in unused_versions(versions, &in_use, keep) {
+            // the image itself goes away with its last tag. not forced, so
+            // anything else still using it keeps it around
+            logger.info(format!("Removing {name} {}", &id[..12.min(id.len())]));
+            match images.get(&tag).delete().await {
+                Err(podman_api::Error::Fault { code, message }) if code == hyper::StatusCode::CONFLICT => {
+                    logger.warn(format!("Keeping {tag}: {message}"));
+                }
+                result => result.d()?,
+            }
         }
     }
 
-    Err(miette::miette!("image stream completed without resolved id"))
+    Ok(())
+}
+
+// the (tag, image id) of every version to remove, out of (first used, tag, image
+// id) for one image name
+fn unused_versions(mut versions: Vec<(u64, String, String)>, in_use: &HashSet<String>, keep: usize) -> Vec<(String, String)> {
+    versions.sort_by(|a, b| b.cmp(a));
+    versions
+        .into_iter()
+        .filter(|(_, _, id)| !in_use.contains(id))
+        .skip(keep)
+        .map(|(_, tag, id)| (tag, id))
+        .collect()
+}
+
+// pulls every image up front, so a slow registry doesn't hold up a sync while
+// containers are down. the image steps take what was pulled from the result
+// instead of pulling again
+pub async fn pre_pull(
+    service: &Podman,
+    credentials: &Credentials,
+    logger: &Logger,
+    actions: Vec<ImageAction>,
+    parallelism: usize,
+) -> miette::Result<BTreeMap<ResolvedImageRef, PrePulled>> {
+    let actions = actions.into_iter().filter(|action| !action.local).collect::<Vec<_>>();
+    let summary = logger.counter(actions.len() as u64, "images");
+    let pulled = futures_util::stream::iter(actions)
+        .map(|action| {
+            let summary = &summary;
+            async move {
+                let resource = format!("image `{}`", action.name);
+                let logger = logger.for_resource(&resource);
+                let progress = logger.spinner(&resource);
+                let (reference, policy, before) = match &action.pinned {
+                    Some(pinned) => (pinned, PullPolicy::Missing, pinned_id(service, pinned).await?),
+                    None => (
+                        &action.reference,
+                        pull_policy(action.pull),
+                        local_id(service, &logger, &action.reference).await?,
+                    ),
+                };
+                let id = pull_image(service, credentials, reference, &action.reference_span, policy, |line| {
+                    progress.set_message(line)
+                })
+                .await?;
+                progress.finish();
+                logger.info(format!("Pulled {}", &id[..12.min(id.len())]));
+                summary.inc();
+                miette::Result::<_>::Ok((action.resolved, PrePulled { before, id }))
+            }
+        })
+        .buffer_unordered(parallelism)
+        .try_collect()
+        .await?;
+    summary.finish();
+
+    Ok(pulled)
+}
+
+// podman checks the registry's manifest digest against the local image for
+// newer, and only pulls if it changed
+fn pull_policy(pull: ParsedPullPolicy) -> PullPolicy {
+    match pull {
+        ParsedPullPolicy::Missing => PullPolicy::Missing,
+        ParsedPullPolicy::Always => PullPolicy::Always,
+        ParsedPullPolicy::Newer => PullPolicy::Newer,
+    }
+}
+
+// pulls an image and returns its id, passing podman's progress messages along
+pub async fn pull_image(
+    service: &Podman,
+    credentials: &Credentials,
+    reference: &str,
+    span: &ParseSpan,
+    policy: PullPolicy,
+    on_message: impl Fn(&str),
+) -> miette::Result<String> {
+    let image_service = service.images();
+    let auth = credentials.for_reference(reference).await?;
+    let mut opts = PullOpts::builder().reference(reference).policy(policy);
+    if let Some(auth) = auth.clone() {
+        opts.auth(auth);
+    }
+    let opts = opts.build();
+    let pulled = Retry::PULL
+        .run(|| async {
+            let mut stream = image_service.pull(&opts);
+            while let Some(report) = stream.try_next().await? {
+                // registry errors come back inside the stream. only the ones where
+                // the registry itself had trouble are worth another try, a missing
+                // tag won't show up by asking again
+                if let Some(message) = report.error {
+                    return Err(if is_auth_failure(&message) {
+                        podman_api::Error::Fault {
+                            code: hyper::StatusCode::UNAUTHORIZED,
+                            message,
+                        }
+                    } else if is_registry_hiccup(&message) {
+                        podman_api::Error::Fault {
+                            code: hyper::StatusCode::BAD_GATEWAY,
+                            message,
+                        }
+                    } else {
+                        podman_api::Error::StringError(message)
+                    });
+                }
+
+                if let Some(line) = report.stream.as_deref().map(str::trim).filter(|line| !line.is_empty()) {
+                    on_message(line);
+                }
+
+                if report.id.is_some() {
+                    return Ok(report.id);
+                }
+            }
+            Ok(None)
+        })
+        .await;
+
+    let pulled = match pulled {
+        Err(podman_api::Error::Fault { code, message })
+            if code == hyper::StatusCode::UNAUTHORIZED || code == hyper::StatusCode::FORBIDDEN =>
+        {
+            Err(RegistryAuthFailed {
+                registry: registry::registry(reference),
+                message,
+                content: read_source(span)?,
+                here: span.source_span(),
+                help: if auth.is_some() {
+                    "the registry turned down the credentials tug found, check them in tug.toml or auth.json"
+                } else {
+                    "no credentials found for it, add them to `registries` in tug.toml or run `podman login`"
+                },
+            })?
+        }
+        pulled => pulled.d()?,
+    };
+
+    pulled.ok_or_else(|| miette::miette!("image stream completed without resolved id"))
+}
+
+// registries word this differently, but all of them say one of these
+// connection trouble on the way to the registry, or the registry answering with
+// a server error or rate limit
+fn is_registry_hiccup(message: &str) -> bool {
+    let message = message.to_lowercase();
+    [
+        "connection reset",
+        "connection refused",
+        "i/o timeout",
+        "tls handshake timeout",
+        "unexpected eof",
+        "too many requests",
+        "toomanyrequests",
+        "500 internal server error",
+        "502 bad gateway",
+        "503 service unavailable",
+        "504 gateway timeout",
+    ]
+    .iter()
+    .any(|needle| message.contains(needle))
+}
+
+fn is_auth_failure(message: &str) -> bool {
+    let message = message.to_lowercase();
+    [
+        "unauthorized",
+        "authentication required",
+        "access to the resource is denied",
+        "invalid username/password",
+    ]
+    .iter()
+    .any(|needle| message.contains(needle))
+}
+
+#[derive(miette::Diagnostic, thiserror::Error, Debug)]
+#[error("couldn't authenticate to {registry}: {message}")]
+struct RegistryAuthFailed {
+    registry: String,
+    message: String,
+    #[source_code]
+    content: NamedSource,
+    #[label("pulled from here")]
+    here: SourceSpan,
+    #[help]
+    help: &'static str,
 }
 
 #[derive(miette::Diagnostic, thiserror::Error, Debug)]
@@ -85,3 +450,41 @@ struct ImageNotFound {
     #[help]
     help: &'static str,
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn version(since: u64, id: &str) -> (u64, String, String) {
+        (since, format!("localhost/tug-default/web:{since}"), id.to_string())
+    }
+
+    fn tag(since: u64, id: &str) -> (String, String) {
+        (format!("localhost/tug-default/web:{since}"), id.to_string())
+    }
+
+    #[test]
+    fn keeps_the_newest_unused() {
+        let versions = vec![version(1, "a"), version(3, "c"), version(2, "b"), version(4, "d")];
+        let in_use = HashSet::from(["d".to_string()]);
+        assert_eq!(unused_versions(versions, &in_use, 2), [tag(1, "a")]);
+    }
+
+    #[test]
+    fn in_use_never_goes() {
+        let versions = vec![version(1, "a"), version(2, "b")];
+        let in_use = HashSet::from(["a".to_string()]);
+        assert_eq!(unused_versions(versions, &in_use, 0), [tag(2, "b")]);
+    }
+
+    #[test]
+    fn nothing_to_remove() {
+        let versions = vec![version(1, "a"), version(2, "b")];
+        assert!(unused_versions(versions, &HashSet::new(), 2).is_empty());
+    }
+
+    #[test]
+    fn tracking_names_are_sanitized() {
+        assert_eq!(tracking_repository("My Group", "web/app"), "localhost/tug-my-group/web-app");
+    }
+}
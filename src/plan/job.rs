@@ -0,0 +1,221 @@
+use std::{
+    collections::{BTreeMap, HashMap},
+    ops::Deref,
+    path::PathBuf,
+    pin::pin,
+};
+
+use base64::{prelude::BASE64_URL_SAFE_NO_PAD, Engine};
+use futures_util::{Stream, StreamExt};
+use miette::Context;
+use podman_api::{
+    conn::TtyChunk,
+    models::{ContainerStatus, ListContainer},
+    opts::{ContainerCreateOpts, ContainerListFilter, ContainerListOpts, ContainerLogsOpts, ContainerWaitOpts},
+    Podman,
+};
+use serde::{Deserialize, Serialize};
+
+use super::{
+    container::{
+        inject_files, inject_fingerprint, network_opts, secret_fingerprint, secret_opts, secret_print_from_fulls, storage_opts,
+        ContainerActionBindMount, ContainerActionNetwork, ContainerActionSecret, ContainerActionVolumeMount, FullSecret,
+        InjectNode, SecretFingerprint,
+    },
+    image::ResolvedImageRef,
+    Outcome, PostAction, Retry, StepContext,
+};
+use crate::{
+    logger::Logger,
+    parse::model::ParsedContainerInject,
+    utils::{IntoDiagnosticShorthand, XTug},
+};
+
+#[derive(Clone, Debug)]
+pub struct JobAction {
+    pub name: String,
+    pub command: Option<Vec<String>>,
+    pub image: ResolvedImageRef,
+    pub env: Vec<(String, String)>,
+    pub injects: Vec<ParsedContainerInject>,
+    pub networks: Vec<ContainerActionNetwork>,
+    pub volumes: Vec<ContainerActionVolumeMount>,
+    pub secrets: Vec<ContainerActionSecret>,
+    pub binds: Vec<ContainerActionBindMount>,
+}
+
+// everything that makes a job run again when it changes
+#[derive(PartialEq, Deserialize, Serialize)]
+pub struct JobFingerprint {
+    #[serde(rename = "i")]
+    image: String,
+    #[serde(rename = "c")]
+    command: Option<Vec<String>>,
+    #[serde(rename = "e")]
+    env: BTreeMap<String, String>,
+    #[serde(rename = "j")]
+    injects: HashMap<PathBuf, InjectNode>,
+    #[serde(rename = "s")]
+    secrets: Vec<SecretFingerprint>,
+}
+
+pub async fn execute(ctx: &StepContext, action: JobAction) -> miette::Result<Outcome> {
+    let (fingerprint, secret_fulls) = job_fingerprint(ctx, &action).await?;
+
+    let remote_jobs = remote_job_query(&ctx.service, ctx.group.clone(), action.name.clone()).await?;
+
+    // a job only counts as done once it has exited cleanly with the same inputs
+    if remote_jobs
+        .iter()
+        .any(|job| job.state.as_deref() == Some("exited") && job.exit_code == Some(0) && fingerprint_matches(job, &fingerprint))
+    {
+        return Ok(Outcome::Unchanged);
+    }
+
+    let id = create_job_container(ctx, &action, &fingerprint, &secret_fulls, XTug::Job).await?;
+    let container = ctx.service.containers().get(&id);
+
+    container.start(None).await.d()?;
+
+    log_output(
+        &ctx.logger,
+        container.logs(&ContainerLogsOpts::builder().follow(true).stdout(true).stderr(true).build()),
+    )
+    .await?;
+
+    container
+        .wait(&ContainerWaitOpts::builder().conditions([ContainerStatus::Exited]).build())
+        .await
+        .d()?;
+    let exit_code = container
+        .inspect()
+        .await
+        .d()?
+        .state
+        .and_then(|state| state.exit_code)
+        .unwrap_or_default();
+    if exit_code != 0 {
+        return Err(miette::miette!("job `{}` exited with code {exit_code}", action.name));
+    }
+
+    let outcome = if remote_jobs.is_empty() {
+        Outcome::Created
+    } else {
+        Outcome::Recreated
+    };
+    for job in remote_jobs {
+        let id = job.id.unwrap();
+        if job.state.as_deref() == Some("running") {
+            ctx.service.containers().get(&id).stop(&Default::default()).await.d()?;
+        }
+        ctx.finalize.lock().push(PostAction::DeleteContainer { id });
+    }
+
+    Ok(outcome)
+}
+
+// logs container output line by line
+pub async fn log_output<E: std::error::Error + Send + Sync + 'static>(
+    logger: &Logger,
+    output: impl Stream<Item = Result<TtyChunk, E>>,
+) -> miette::Result<()> {
+    let mut output = pin!(output);
+    let mut pending = Vec::new();
+    while let Some(chunk) = output.next().await {
+        pending.extend(Vec::from(chunk.d()?));
+        while let Some(end) = pending.iter().position(|byte| *byte == b'\n') {
+            let line = pending.drain(..=end).collect::<Vec<_>>();
+            logger.info(String::from_utf8_lossy(&line[..end]));
+        }
+    }
+    if !pending.is_empty() {
+        logger.info(String::from_utf8_lossy(&pending));
+    }
+
+    Ok(())
+}
+
+pub async fn job_fingerprint(ctx: &StepContext, action: &JobAction) -> miette::Result<(JobFingerprint, Vec<FullSecret>)> {
+    let mut injects = HashMap::new();
+    for inject in &action.injects {
+        let (node, _) = inject_fingerprint(ctx, inject, None)
+            .await
+            .wrap_err_with(|| format!("calculating fingerprint for {inject:?}"))?;
+        injects.insert(inject.at.deref().clone(), node);
+    }
+    let (secret_fulls, _) = secret_fingerprint(ctx, &action.secrets, None).await?;
+
+    Ok((
+        JobFingerprint {
+            image: ctx.resolved_images.lock()[&action.image].to_string(),
+            command: action.command.clone(),
+            env: action.env.iter().cloned().collect(),
+            injects,
+            secrets: secret_print_from_fulls(&secret_fulls),
+        },
+        secret_fulls,
+    ))
+}
+
+pub fn fingerprint_matches(container: &ListContainer, fingerprint: &JobFingerprint) -> bool {
+    container
+        .labels
+        .as_ref()
+        .and_then(|labels| labels.get(XTug::JobFingerprint.as_ref()))
+        .and_then(|compare| BASE64_URL_SAFE_NO_PAD.decode(compare).ok())
+        .and_then(|compare| rmp_serde::from_slice::<JobFingerprint>(&compare).ok())
+        .as_ref()
+        == Some(fingerprint)
+}
+
+// creates the container without starting it, labelled with `kind` so jobs and
+// schedules can share everything else
+pub async fn create_job_container(
+    ctx: &StepContext,
+    action: &JobAction,
+    fingerprint: &JobFingerprint,
+    secret_fulls: &[FullSecret],
+    kind: XTug,
+) -> miette::Result<String> {
+    let opts = storage_opts(
+        ctx,
+        ContainerCreateOpts::builder()
+            .image(&fingerprint.image)
+            .env(action.env.clone()),
+        &action.volumes,
+        &action.binds,
+    );
+    let mut opts = secret_opts(network_opts(ctx, opts, &action.networks, &[]), secret_fulls);
+    if let Some(command) = &action.command {
+        opts = opts.command(command);
+    }
+    opts = opts.labels([
+        (XTug::Group.to_string(), ctx.group.clone()),
+        (kind.to_string(), action.name.clone()),
+        (
+            XTug::JobFingerprint.to_string(),
+            BASE64_URL_SAFE_NO_PAD.encode(rmp_serde::to_vec(fingerprint).d()?),
+        ),
+    ]);
+
+    let new_container = ctx.service.containers().create(&opts.build()).await.d()?;
+    ctx.backtrack.lock().push(PostAction::DeleteContainer {
+        id: new_container.id.clone(),
+    });
+
+    inject_files(ctx, &new_container.id, &action.injects).await?;
+
+    Ok(new_container.id)
+}
+
+pub async fn remote_job_query(service: &Podman, group: String, name: String) -> miette::Result<Vec<ListContainer>> {
+    let opts = ContainerListOpts::builder()
+        .all(true)
+        .filter([
+            ContainerListFilter::LabelKeyVal(XTug::Group.to_string(), group),
+            ContainerListFilter::LabelKeyVal(XTug::Job.to_string(), name),
+        ])
+        .build();
+    let containers = service.containers();
+    Retry::QUERY.run(|| containers.list(&opts)).await.d()
+}
@@ -1,39 +1,67 @@
 use std::{
     collections::{BTreeMap, BTreeSet},
+    fmt::Display,
+    future::Future,
     path::{Path, PathBuf},
     sync::Arc,
+    time::{Duration, Instant},
 };
 
-use futures_util::future::TryJoinAll;
 use miette::Context;
 use parking_lot::Mutex;
 use podman_api::Podman;
-use tokio::{sync::mpsc, task::JoinHandle};
+use serde::Serialize;
+#[cfg(unix)]
+use tokio::signal::unix::{signal, SignalKind};
+use tokio::{
+    sync::{mpsc, watch},
+    task::{JoinError, JoinHandle},
+};
 
 use self::{
     container::ContainerAction,
     garbage::GarbageAction,
-    image::{ImageAction, ResolvedImageRef},
+    image::{ImageAction, PrePulled, ResolvedImageRef},
+    job::JobAction,
     network::{NetworkAction, ResolvedNetworkRef},
+    pod::{PodAction, ResolvedPodRef},
+    report::{Report, StepReport},
+    schedule::{ScheduleAction, Units},
     secret::{ResolvedSecretRef, SecretAction},
     volume::{ResolvedVolumeRef, VolumeAction},
 };
-use crate::{config::Config, logger::Logger, utils::IntoDiagnosticShorthand};
+use crate::{
+    config::Config,
+    logger::{Logger, Progress},
+    registry::Credentials,
+    utils::{IntoDiagnosticShorthand, RawService},
+};
 
 pub mod container;
 pub mod garbage;
+pub mod hook;
 pub mod image;
+pub mod job;
 pub mod network;
+pub mod pod;
+pub mod report;
+pub mod schedule;
 pub mod secret;
 pub mod volume;
 
+const DEFAULT_PARALLELISM: usize = 5;
+const DEFAULT_STEP_TIMEOUT: Duration = Duration::from_secs(15 * 60);
+
 pub struct Executor {
     pub steps: Vec<Arc<Mutex<Step>>>,
+    pub parallelism: usize,
+    pub step_timeout: Duration,
+    pub keep_going: bool,
+    pub pre_sync: Vec<Vec<String>>,
+    pub post_sync: Vec<Vec<String>>,
     pub failures: Arc<Mutex<Vec<miette::Report>>>,
     completions_tx: mpsc::Sender<(usize, Option<miette::Report>)>,
     completions_rx: mpsc::Receiver<(usize, Option<miette::Report>)>,
-    backtrack: Arc<Mutex<Vec<PostAction>>>,
-    finalize: Arc<Mutex<Vec<PostAction>>>,
 }
 
 impl Executor {
@@ -41,37 +69,65 @@ impl Executor {
         let (completions_tx, completions_rx) = mpsc::channel(10);
         Executor {
             steps: Default::default(),
+            parallelism: DEFAULT_PARALLELISM,
+            step_timeout: DEFAULT_STEP_TIMEOUT,
+            keep_going: false,
+            pre_sync: Default::default(),
+            post_sync: Default::default(),
             failures: Default::default(),
             completions_tx,
             completions_rx,
-            backtrack: Default::default(),
-            finalize: Default::default(),
         }
     }
 
+    pub fn add_dependency(&mut self, id: usize, on: usize) {
+        self.steps[id].lock().depends_on.insert(on);
+    }
+
     pub fn new_step(&mut self, action: Action, depends_on: BTreeSet<usize>) -> usize {
         let id = self.steps.len();
         self.steps.push(Arc::new(Mutex::new(Step {
             id,
             action,
+            dependencies: depends_on.clone(),
             depends_on,
             status: StepStatus::Queued,
+            duration: None,
+            backtrack: Default::default(),
+            finalize: Default::default(),
         })));
         id
     }
 
+    pub fn image_actions(&self) -> Vec<ImageAction> {
+        self.steps
+            .iter()
+            .filter_map(|step| match &step.lock().action {
+                Action::Image(action) => Some(action.clone()),
+                _ => None,
+            })
+            .collect()
+    }
+
+    pub fn set_pre_pulled(&mut self, mut pulled: BTreeMap<ResolvedImageRef, PrePulled>) {
+        for step in &self.steps {
+            if let Action::Image(action) = &mut step.lock().action {
+                action.pre_pulled = pulled.remove(&action.resolved);
+            }
+        }
+    }
+
     pub async fn execute(
         &mut self,
         config: &Config,
         logger: &Logger,
         service: Podman,
         root_directory: &Path,
-    ) -> miette::Result<()> {
-        const DEFAULT_CONCURRENCY_LIMIT: usize = 5;
-
+        interrupts: &Interrupts,
+    ) -> miette::Result<Report> {
         if self.steps.is_empty() {
             logger.trace("No steps");
-            return Ok(());
+            return Ok(Report::default());
         }
 
         let mut to_start = Vec::new();
@@ -86,39 +142,76 @@ impl Executor {
             }
         }
 
-        let mut concurrency_limit = DEFAULT_CONCURRENCY_LIMIT;
+        // failures outside of steps can't be pinned on a subtree, so they roll back
+        // everything even with keep_going
+        let mut aborted = false;
+
+        // nothing has happened yet if a pre-sync hook fails, so skip straight to
+        // reporting it
+        for command in &self.pre_sync {
+            if let Err(err) = hook::run_local(logger, command, root_directory).await {
+                self.failures.lock().push(err.wrap_err("running pre-sync hook"));
+                aborted = true;
+                to_start.clear();
+                break;
+            }
+        }
+
+        let mut available = self.parallelism;
         let resolved_images: Arc<Mutex<BTreeMap<ResolvedImageRef, String>>> = Default::default();
         let resolved_networks: Arc<Mutex<BTreeMap<ResolvedNetworkRef, String>>> = Default::default();
         let resolved_volumes: Arc<Mutex<BTreeMap<ResolvedVolumeRef, String>>> = Default::default();
         let resolved_secrets: Arc<Mutex<BTreeMap<ResolvedSecretRef, String>>> = Default::default();
+        let resolved_pods: Arc<Mutex<BTreeMap<ResolvedPodRef, String>>> = Default::default();
+
+        // steps that are already running are left to finish so the backtrack list
+        // is complete when it runs
+        let mut interrupts = interrupts.handle();
+        let mut interrupted = false;
+
+        let summary = logger.counter(self.steps.len() as u64, "steps");
 
         logger.trace("Entering main loop");
         loop {
             // check if we're done
-            if to_start.is_empty() && concurrency_limit == DEFAULT_CONCURRENCY_LIMIT {
+            if to_start.is_empty() && available == self.parallelism {
                 logger.trace("Done");
                 break;
             }
 
             // start executing new steps
-            while concurrency_limit > 1 {
+            while available > 0 {
                 if let Some(id) = to_start.pop() {
                     logger.trace(format!("Executing {id}"));
-                    concurrency_limit -= 1;
+                    available -= 1;
                     let step = &self.steps[id];
-                    {
-                        step.lock().status = StepStatus::Running;
-                    }
+                    let (backtrack, finalize, progress, step_logger) = {
+                        let mut step = step.lock();
+                        step.status = StepStatus::Running;
+                        (
+                            step.backtrack.clone(),
+                            step.finalize.clone(),
+                            logger.spinner(&step.action),
+                            logger.for_step(id, &step.action),
+                        )
+                    };
                     let ctx = StepContext {
                         service: service.clone(),
+                        raw_service: config.raw_service(),
                         resolved_images: resolved_images.clone(),
                         resolved_networks: resolved_networks.clone(),
                         resolved_volumes: resolved_volumes.clone(),
                         resolved_secrets: resolved_secrets.clone(),
+                        resolved_pods: resolved_pods.clone(),
                         group: config.group.clone(),
                         root_directory: root_directory.to_path_buf(),
-                        backtrack: self.backtrack.clone(),
-                        finalize: self.finalize.clone(),
+                        identity: config.identity.clone(),
+                        credentials: config.credentials(),
+                        logger: step_logger,
+                        timeout: self.step_timeout,
+                        progress,
+                        backtrack,
+                        finalize,
                     };
                     tokio::spawn(Step::execute(ctx, step.clone(), self.completions_tx.clone()));
                 } else {
@@ -129,21 +222,36 @@ impl Executor {
 
             // wait for the next step to complete
             logger.trace("Waiting for next step to complete");
-            let (completed_id, failure_state) = self
-                .completions_rx
-                .recv()
-                .await
-                .expect("sender half should never be dropped before receiver half");
+            let (completed_id, failure_state) = tokio::select! {
+                completion = self.completions_rx.recv() => {
+                    completion.expect("sender half should never be dropped before receiver half")
+                }
+                _ = interrupts.recv() => {
+                    logger.warn("Interrupted, waiting for running steps to finish (interrupt again to force exit)");
+                    interrupted = true;
+                    self.failures.lock().push(miette::miette!("interrupted"));
+                    aborted = true;
+                    break;
+                }
+            };
             logger.trace(format!("Step {completed_id} completed"));
 
-            concurrency_limit += 1;
+            available += 1;
+            self.report_completion(logger, &summary, completed_id);
 
             if let Some(failure) = failure_state {
                 let step = self.steps[completed_id].lock();
                 logger.trace(format!("Step {completed_id} {step:?} reached failure state {failure:?}"));
                 drop(step);
                 self.failures.lock().push(failure);
-                break;
+                if !self.keep_going {
+                    break;
+                }
+                // skipped steps count as done, or the bar never gets to the end
+                for skipped in self.skip_dependents(completed_id) {
+                    self.report_completion(logger, &summary, skipped);
+                }
+                continue;
             }
 
             // queue new steps
@@ -161,13 +269,32 @@ impl Executor {
             }
         }
 
-        while concurrency_limit < DEFAULT_CONCURRENCY_LIMIT {
+        // nothing after this point can stop halfway cleanly, so an interrupt only
+        // warns and a second one exits
+        let _cleanup = AbortOnDrop(tokio::spawn({
+            let logger = logger.clone();
+            // the handle itself stays out here so it's let go of as soon as this
+            // returns
+            let mut interrupts = interrupts.0.clone();
+            async move {
+                if !interrupted {
+                    interrupts.recv().await;
+                    logger.warn("Interrupted, finishing up first (interrupt again to force exit)");
+                }
+                interrupts.recv().await;
+                logger.warn("Forcing exit");
+                std::process::exit(130);
+            }
+        }));
+
+        while available < self.parallelism {
             let (completed_id, failure_state) = self
                 .completions_rx
                 .recv()
                 .await
                 .expect("sender half should never be dropped before receiver half");
             logger.trace(format!("Post-completion from {completed_id}"));
+            self.report_completion(logger, &summary, completed_id);
 
             if let Some(failure) = failure_state {
                 logger.trace(format!(
@@ -176,49 +303,170 @@ impl Executor {
                 self.failures.lock().push(failure);
             }
 
-            concurrency_limit += 1;
+            available += 1;
         }
+        summary.finish();
 
         if self.failures.lock().is_empty() {
-            logger.log("Finalizing");
+            for command in &self.post_sync {
+                if let Err(err) = hook::run_local(logger, command, root_directory).await {
+                    self.failures.lock().push(err.wrap_err("running post-sync hook"));
+                    aborted = true;
+                    break;
+                }
+            }
+        }
+
+        let failed = !self.failures.lock().is_empty();
+        // with keep_going only the subtrees that failed are undone, and everything
+        // that made it through is kept
+        let (undone, withheld) = if failed && (aborted || !self.keep_going) {
+            ((0..self.steps.len()).collect(), BTreeSet::new())
+        } else {
+            let steps = self.steps.iter().map(|step| step.lock()).collect::<Vec<_>>();
+            undone_steps(
+                &steps
+                    .iter()
+                    .map(|step| (matches!(step.status, StepStatus::Succeeded(_)), &step.dependencies))
+                    .collect::<Vec<_>>(),
+            )
+        };
+        let mut backtrack = Vec::new();
+        let mut finalize = Vec::new();
+        for step in &self.steps {
+            let mut step = step.lock();
+            if undone.contains(&step.id) {
+                let mut actions = step.backtrack.lock().split_off(0);
+                // a step that got undone shouldn't be reported as whatever it did
+                if let (StepStatus::Succeeded(outcome), false) = (&step.status, actions.is_empty()) {
+                    step.status = StepStatus::RolledBack(*outcome);
+                }
+                backtrack.append(&mut actions);
+            } else if !withheld.contains(&step.id) {
+                finalize.append(&mut step.finalize.lock());
+            }
+        }
+
+        if !failed {
+            logger.info("Finalizing");
             logger.trace("Executing finalize");
-            queue_post_action(&mut self.finalize, &service)
+            queue_post_action(finalize, &service)
                 .await
                 .d()?
                 .into_iter()
                 .collect::<Result<_, _>>()
                 .d()?;
         } else {
-            logger.log("Failure state triggered, attempting to recover");
+            logger.error("Failure state triggered, attempting to recover");
             logger.trace("Executing backtrack");
-            let joins = queue_post_action(&mut self.backtrack, &service).await.d()?;
+            for action in &backtrack {
+                logger.info(format!("Rolling back: {action}"));
+            }
+            let mut joins = queue_post_action(backtrack, &service).await.d()?;
+            if !finalize.is_empty() {
+                logger.info("Finalizing steps that succeeded");
+                logger.trace("Executing finalize");
+                joins.extend(queue_post_action(finalize, &service).await.d()?);
+            }
 
             if joins.iter().any(|join| join.is_err()) {
-                logger.log("Error(s) while attempting to recover:");
+                logger.error("Error(s) while attempting to recover:");
                 for join in joins {
-                    if let Err(err) = join {
-                        println!("{err:?}");
+                    if let Err(err) = join.d() {
+                        logger.failure(&err);
                     }
                 }
             }
 
-            logger.log("Error(s) while attempting to execute:");
+            logger.error("Error(s) while attempting to execute:");
             for failure in self.failures.lock().iter() {
-                println!("{failure:?}");
+                logger.failure(failure);
             }
         }
 
-        Ok(())
+        let resolved_images = resolved_images.lock();
+        Ok(Report {
+            succeeded: !failed,
+            steps: self.steps.iter().map(|step| StepReport::new(&step.lock())).collect(),
+            images: self
+                .steps
+                .iter()
+                .filter_map(|step| match &step.lock().action {
+                    Action::Image(action) => Some((action.name.clone(), resolved_images.get(&action.resolved)?.clone())),
+                    _ => None,
+                })
+                .collect(),
+        })
+    }
+
+    // finished steps collapse into the summary line, or get a line each when
+    // there's no progress display
+    fn report_completion(&self, logger: &Logger, summary: &Progress, id: usize) {
+        let mut counts = BTreeMap::<_, usize>::new();
+        for step in &self.steps {
+            let outcome = match &step.lock().status {
+                StepStatus::Succeeded(outcome) => *outcome,
+                StepStatus::Failed(_) => Outcome::Failed,
+                StepStatus::Skipped(_) => Outcome::Skipped,
+                StepStatus::RolledBack(_) => Outcome::RolledBack,
+                StepStatus::Queued | StepStatus::Running => continue,
+            };
+            *counts.entry(outcome.to_string()).or_default() += 1;
+        }
+        summary.inc();
+        summary.set_message(
+            counts
+                .iter()
+                .map(|(outcome, count)| format!("{count} {outcome}"))
+                .collect::<Vec<_>>()
+                .join(", "),
+        );
+
+        if !logger.interactive() {
+            let step = self.steps[id].lock();
+            match &step.status {
+                StepStatus::Succeeded(outcome) => logger.info(format!("{}: {outcome}", step.action)),
+                StepStatus::Failed(reason) => logger.error(format!("{}: failed: {reason}", step.action)),
+                StepStatus::Skipped(reason) => logger.info(format!("{}: skipped: {reason}", step.action)),
+                _ => {}
+            }
+        }
+    }
+
+    // marks everything downstream of a failed step as skipped so it never starts
+    fn skip_dependents(&self, failed: usize) -> Vec<usize> {
+        let reason = format!("depends on {}, which failed", self.steps[failed].lock().action);
+        let mut skipped = Vec::new();
+        let mut blocked = vec![failed];
+        while let Some(id) = blocked.pop() {
+            for step in &self.steps {
+                let mut step = step.lock();
+                if step.status == StepStatus::Queued && step.depends_on.contains(&id) {
+                    step.status = StepStatus::Skipped(reason.clone());
+                    blocked.push(step.id);
+                    skipped.push(step.id);
+                }
+            }
+        }
+        skipped
     }
 }
 
 pub struct StepContext {
     pub service: Podman,
+    pub raw_service: RawService,
     pub resolved_images: Arc<Mutex<BTreeMap<ResolvedImageRef, String>>>,
     pub resolved_networks: Arc<Mutex<BTreeMap<ResolvedNetworkRef, String>>>,
     pub resolved_volumes: Arc<Mutex<BTreeMap<ResolvedVolumeRef, String>>>,
     pub resolved_secrets: Arc<Mutex<BTreeMap<ResolvedSecretRef, String>>>,
+    pub resolved_pods: Arc<Mutex<BTreeMap<ResolvedPodRef, String>>>,
     pub root_directory: PathBuf,
+    // as configured, None for the default
+    pub identity: Option<PathBuf>,
+    pub credentials: Credentials,
+    pub logger: Logger,
+    pub timeout: Duration,
+    pub progress: Progress,
     pub group: String,
     pub backtrack: Arc<Mutex<Vec<PostAction>>>,
     pub finalize: Arc<Mutex<Vec<PostAction>>>,
@@ -229,28 +477,54 @@ pub struct Step {
     pub id: usize,
     pub action: Action,
     pub status: StepStatus,
+    pub duration: Option<Duration>,
     depends_on: BTreeSet<usize>,
+    // depends_on empties out as steps complete, this doesn't
+    dependencies: BTreeSet<usize>,
+    // post actions are kept per step so keep_going can undo just the steps that
+    // failed
+    backtrack: Arc<Mutex<Vec<PostAction>>>,
+    finalize: Arc<Mutex<Vec<PostAction>>>,
 }
 
 impl Step {
     pub async fn execute(ctx: StepContext, step: Arc<Mutex<Step>>, completions: mpsc::Sender<(usize, Option<miette::Report>)>) {
         let action = step.lock().action.clone();
-        let failure_state = match action {
-            Action::Container(action) => container::execute(&ctx, action).await.wrap_err("executing container step"),
-            Action::Image(action) => image::execute(&ctx, action).await.wrap_err("executing image step"),
-            Action::Garbage(action) => garbage::execute(&ctx, action).await.wrap_err("executing garbage step"),
-            Action::Network(action) => network::execute(&ctx, action).await.wrap_err("executing network step"),
-            Action::Volume(action) => volume::execute(&ctx, action).await.wrap_err("executing volume step"),
-            Action::Secret(action) => secret::execute(&ctx, action).await.wrap_err("executing secret step"),
-        }
-        .err();
+        let kind = action.kind();
+        let started = Instant::now();
+        let run = async {
+            match action {
+                Action::Container(action) => container::execute(&ctx, action).await.wrap_err("executing container step"),
+                Action::Image(action) => image::execute(&ctx, action).await.wrap_err("executing image step"),
+                Action::Garbage(action) => garbage::execute(&ctx, action).await.wrap_err("executing garbage step"),
+                Action::Network(action) => network::execute(&ctx, action).await.wrap_err("executing network step"),
+                Action::Volume(action) => volume::execute(&ctx, action).await.wrap_err("executing volume step"),
+                Action::Secret(action) => secret::execute(&ctx, action).await.wrap_err("executing secret step"),
+                Action::Pod(action) => pod::execute(&ctx, action).await.wrap_err("executing pod step"),
+                Action::Job(action) => job::execute(&ctx, action).await.wrap_err("executing job step"),
+                Action::Schedule(action) => schedule::execute(&ctx, action).await.wrap_err("executing schedule step"),
+            }
+        };
+        let result = match tokio::time::timeout(ctx.timeout, run).await {
+            Ok(result) => result,
+            Err(_) => Err(StepTimedOut {
+                kind,
+                seconds: ctx.timeout.as_secs(),
+            }
+            .into()),
+        };
 
         let id = {
             let mut step = step.lock();
-            step.status = StepStatus::Complete;
+            step.status = match result {
+                Ok(outcome) => StepStatus::Succeeded(outcome),
+                Err(ref failure) => StepStatus::Failed(failure.root_cause().to_string()),
+            };
+            step.duration = Some(started.elapsed());
+            ctx.progress.finish();
             step.id
         };
-        completions.send((id, failure_state)).await.unwrap();
+        completions.send((id, result.err())).await.unwrap();
     }
 }
 
@@ -258,7 +532,67 @@ impl Step {
 pub enum StepStatus {
     Queued,
     Running,
-    Complete,
+    Succeeded(Outcome),
+    Failed(String),
+    Skipped(String),
+    // succeeded with the outcome, then got undone after something else failed
+    RolledBack(Outcome),
+}
+
+// with keep_going only the steps that didn't succeed get rolled back. whatever
+// they depend on (the network they share with a sibling, say) stays up, but
+// isn't finalized either, since finalizing would throw away the old state a
+// rolled back step went back to. returns the steps to roll back and the steps
+// to leave alone
+fn undone_steps(steps: &[(bool, &BTreeSet<usize>)]) -> (BTreeSet<usize>, BTreeSet<usize>) {
+    let undone = (0..steps.len()).filter(|id| !steps[*id].0).collect::<BTreeSet<_>>();
+    let mut withheld = BTreeSet::new();
+    let mut pending = undone.iter().copied().collect::<Vec<_>>();
+    while let Some(id) = pending.pop() {
+        for dependency in steps[id].1 {
+            if !undone.contains(dependency) && withheld.insert(*dependency) {
+                pending.push(*dependency);
+            }
+        }
+    }
+    (undone, withheld)
+}
+
+// what a step did, or why it didn't
+#[derive(Clone, Copy, PartialEq, Eq, Debug, Serialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum Outcome {
+    Created,
+    Unchanged,
+    Recreated,
+    Updated,
+    Deleted,
+    Failed,
+    Skipped,
+    RolledBack,
+}
+
+impl Display for Outcome {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(match self {
+            Outcome::Created => "created",
+            Outcome::Unchanged => "unchanged",
+            Outcome::Recreated => "recreated",
+            Outcome::Updated => "updated",
+            Outcome::Deleted => "deleted",
+            Outcome::Failed => "failed",
+            Outcome::Skipped => "skipped",
+            Outcome::RolledBack => "rolled back",
+        })
+    }
+}
+
+#[derive(miette::Diagnostic, thiserror::Error, Debug)]
+#[error("{kind} step took longer than {seconds}s")]
+#[diagnostic(help("raise the limit with --step-timeout if it's expected to take this long"))]
+struct StepTimedOut {
+    kind: &'static str,
+    seconds: u64,
 }
 
 #[derive(Clone, Debug)]
@@ -269,24 +603,247 @@ pub enum Action {
     Network(NetworkAction),
     Volume(VolumeAction),
     Secret(SecretAction),
+    Pod(PodAction),
+    Job(JobAction),
+    Schedule(ScheduleAction),
 }
 
+impl Action {
+    pub fn kind(&self) -> &'static str {
+        match self {
+            Action::Container(_) => "container",
+            Action::Image(_) => "image",
+            Action::Garbage(_) => "garbage",
+            Action::Network(_) => "network",
+            Action::Volume(_) => "volume",
+            Action::Secret(_) => "secret",
+            Action::Pod(_) => "pod",
+            Action::Job(_) => "job",
+            Action::Schedule(_) => "schedule",
+        }
+    }
+
+    pub fn name(&self) -> Option<&str> {
+        match self {
+            Action::Container(action) => Some(&action.name),
+            Action::Image(action) => Some(&action.name),
+            Action::Garbage(_) => None,
+            Action::Network(action) => Some(&action.name),
+            Action::Volume(action) => Some(&action.name),
+            Action::Secret(action) => Some(&action.name),
+            Action::Pod(action) => Some(&action.name),
+            Action::Job(action) => Some(&action.name),
+            Action::Schedule(action) => Some(&action.job.name),
+        }
+    }
+}
+
+impl Display for Action {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Action::Container(action) if action.replica > 0 => {
+                write!(f, "container `{}` (replica {})", action.name, action.replica)
+            }
+            Action::Container(action) => write!(f, "container `{}`", action.name),
+            Action::Image(action) => write!(f, "image `{}`", action.name),
+            Action::Garbage(_) => write!(f, "garbage collection"),
+            Action::Network(action) => write!(f, "network `{}`", action.name),
+            Action::Volume(action) => write!(f, "volume `{}`", action.name),
+            Action::Secret(action) => write!(f, "secret `{}`", *action.name),
+            Action::Pod(action) => write!(f, "pod `{}`", action.name),
+            Action::Job(action) => write!(f, "job `{}`", action.name),
+            Action::Schedule(action) => write!(f, "schedule `{}`", action.job.name),
+        }
+    }
+}
+
+#[derive(Debug)]
 pub enum PostAction {
     DeleteContainer { id: String },
     RestartContainer { id: String },
     DeleteNetwork { id: String },
     DeleteVolume { name: String },
+    DeleteSecret { id: String },
+    DeletePod { id: String },
+    RestartPod { id: String },
+    DeleteSchedule { unit: String },
+    RestoreSchedule { unit: String, units: Option<Units> },
+}
+
+impl Display for PostAction {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            PostAction::DeleteContainer { id } => write!(f, "delete container {id}"),
+            PostAction::RestartContainer { id } => write!(f, "restart container {id}"),
+            PostAction::DeleteNetwork { id } => write!(f, "delete network {id}"),
+            PostAction::DeleteVolume { name } => write!(f, "delete volume {name}"),
+            PostAction::DeleteSecret { id } => write!(f, "delete secret {id}"),
+            PostAction::DeletePod { id } => write!(f, "delete pod {id}"),
+            PostAction::RestartPod { id } => write!(f, "restart pod {id}"),
+            PostAction::DeleteSchedule { unit } => write!(f, "delete schedule {unit}"),
+            PostAction::RestoreSchedule { unit, .. } => write!(f, "restore schedule {unit}"),
+        }
+    }
+}
+
+impl PostAction {
+    // containers have to be gone before the pods, networks and volumes they use can
+    // be deleted, and whatever replaced an old container has to be gone before
+    // it can get its ports back. timers go first so they don't fire at a
+    // container that's being deleted
+    fn phase(&self) -> usize {
+        match self {
+            PostAction::DeleteSchedule { .. } => 0,
+            PostAction::DeleteContainer { .. } => 1,
+            PostAction::DeletePod { .. } => 2,
+            PostAction::RestartContainer { .. } | PostAction::RestartPod { .. } | PostAction::RestoreSchedule { .. } => 3,
+            PostAction::DeleteNetwork { .. } | PostAction::DeleteVolume { .. } | PostAction::DeleteSecret { .. } => 4,
+        }
+    }
+}
+
+// how many times an idempotent call is attempted before giving up, and how
+// long to wait before the first retry. the wait doubles after every attempt
+#[derive(Clone, Copy)]
+pub struct Retry {
+    attempts: u32,
+    delay: Duration,
+}
+
+impl Retry {
+    pub const QUERY: Retry = Retry {
+        attempts: 3,
+        delay: Duration::from_millis(250),
+    };
+    // registries are flakier than the podman service and a pull is worth
+    // waiting for
+    pub const PULL: Retry = Retry {
+        attempts: 5,
+        delay: Duration::from_secs(1),
+    };
+
+    pub async fn run<T, F, Fut>(self, mut call: F) -> Result<T, podman_api::Error>
+    where
+        F: FnMut() -> Fut,
+        Fut: Future<Output = Result<T, podman_api::Error>>,
+    {
+        let mut attempt = 1;
+        let mut delay = self.delay;
+        loop {
+            match call().await {
+                Err(err) if attempt < self.attempts && is_transient(&err) => {
+                    tokio::time::sleep(delay).await;
+                    attempt += 1;
+                    delay *= 2;
+                }
+                result => return result,
+            }
+        }
+    }
+}
+
+fn is_transient(err: &podman_api::Error) -> bool {
+    use podman_api::{conn, Error};
+
+    let retryable = |code: &hyper::StatusCode| code.is_server_error() || *code == hyper::StatusCode::TOO_MANY_REQUESTS;
+    match err {
+        Error::IO(_) | Error::Error(conn::Error::Hyper(_) | conn::Error::IO(_)) => true,
+        Error::Fault { code, .. } | Error::Error(conn::Error::Fault { code, .. }) => retryable(code),
+        _ => false,
+    }
+}
+
+// tokio can't hand signals back to the os once it's listening for them, so this
+// listens once for the whole process and counts them. whatever isn't being
+// handled is left to main, which exits like it would have without it
+#[derive(Clone)]
+pub struct Interrupts {
+    count: watch::Receiver<usize>,
+    handlers: Arc<Mutex<usize>>,
+}
+
+impl Interrupts {
+    pub fn new() -> std::io::Result<Interrupts> {
+        let (count_tx, count) = watch::channel(0);
+        #[cfg(unix)]
+        let (mut interrupt, mut terminate) = (signal(SignalKind::interrupt())?, signal(SignalKind::terminate())?);
+        tokio::spawn(async move {
+            loop {
+                #[cfg(unix)]
+                tokio::select! {
+                    _ = interrupt.recv() => {}
+                    _ = terminate.recv() => {}
+                }
+                #[cfg(not(unix))]
+                if tokio::signal::ctrl_c().await.is_err() {
+                    return;
+                }
+                count_tx.send_modify(|count| *count += 1);
+            }
+        });
+        Ok(Interrupts {
+            count,
+            handlers: Default::default(),
+        })
+    }
+
+    // interrupts from now on go to the handle until it's dropped
+    pub fn handle(&self) -> InterruptHandle {
+        *self.handlers.lock() += 1;
+        let mut interrupts = self.clone();
+        interrupts.count.borrow_and_update();
+        InterruptHandle(interrupts)
+    }
+
+    // the next interrupt nobody is handling
+    pub async fn unhandled(&mut self) {
+        loop {
+            self.recv().await;
+            if *self.handlers.lock() == 0 {
+                return;
+            }
+        }
+    }
+
+    async fn recv(&mut self) {
+        if self.count.changed().await.is_err() {
+            std::future::pending().await
+        }
+    }
+}
+
+pub struct InterruptHandle(Interrupts);
+
+impl InterruptHandle {
+    pub async fn recv(&mut self) {
+        self.0.recv().await
+    }
 }
 
-fn queue_post_action(
-    actions: &mut Arc<Mutex<Vec<PostAction>>>,
-    service: &Podman,
-) -> TryJoinAll<JoinHandle<Result<(), podman_api::Error>>> {
-    futures_util::future::try_join_all(
-        std::mem::take(Arc::get_mut(actions).unwrap())
-            .into_inner()
-            .into_iter()
-            .map(|action| {
+impl Drop for InterruptHandle {
+    fn drop(&mut self) {
+        *self.0.handlers.lock() -= 1;
+    }
+}
+
+struct AbortOnDrop(JoinHandle<()>);
+
+impl Drop for AbortOnDrop {
+    fn drop(&mut self) {
+        self.0.abort();
+    }
+}
+
+async fn queue_post_action(actions: Vec<PostAction>, service: &Podman) -> Result<Vec<Result<(), podman_api::Error>>, JoinError> {
+    let mut phases = BTreeMap::<_, Vec<_>>::new();
+    for action in actions {
+        phases.entry(action.phase()).or_default().push(action);
+    }
+
+    let mut results = Vec::new();
+    for (_, actions) in phases {
+        results.extend(
+            futures_util::future::try_join_all(actions.into_iter().map(|action| {
                 let service = service.clone();
                 match action {
                     PostAction::DeleteContainer { id } => {
@@ -299,7 +856,50 @@ fn queue_post_action(
                         tokio::spawn(async move { service.networks().get(id).remove().await.map(|_| {}) })
                     }
                     PostAction::DeleteVolume { name } => tokio::spawn(async move { service.volumes().get(name).remove().await }),
+                    PostAction::DeleteSecret { id } => tokio::spawn(async move { service.secrets().get(id).delete().await }),
+                    PostAction::DeletePod { id } => {
+                        tokio::spawn(async move { service.pods().get(id).remove().await.map(|_| {}) })
+                    }
+                    PostAction::RestartPod { id } => {
+                        tokio::spawn(async move { service.pods().get(id).start().await.map(|_| {}) })
+                    }
+                    PostAction::DeleteSchedule { unit } => {
+                        tokio::spawn(async move { Ok(schedule::write_units(&unit, None).await?) })
+                    }
+                    PostAction::RestoreSchedule { unit, units } => {
+                        tokio::spawn(async move { Ok(schedule::write_units(&unit, units.as_ref()).await?) })
+                    }
                 }
-            }),
-    )
+            }))
+            .await?,
+        );
+    }
+
+    Ok(results)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn shared_network() {
+        // a network, and containers a and b on it, where b failed
+        let none = BTreeSet::new();
+        let network = BTreeSet::from([0]);
+        let (undone, withheld) = undone_steps(&[(true, &none), (true, &network), (false, &network)]);
+        assert_eq!(undone, BTreeSet::from([2]));
+        assert_eq!(withheld, BTreeSet::from([0]));
+    }
+
+    #[test]
+    fn transitive() {
+        // an image under the network is held back too
+        let none = BTreeSet::new();
+        let image = BTreeSet::from([0]);
+        let network = BTreeSet::from([1]);
+        let (undone, withheld) = undone_steps(&[(true, &none), (true, &image), (false, &network)]);
+        assert_eq!(undone, BTreeSet::from([2]));
+        assert_eq!(withheld, BTreeSet::from([0, 1]));
+    }
 }
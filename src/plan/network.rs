@@ -4,7 +4,7 @@ use podman_api::{
     Podman,
 };
 
-use super::{PostAction, StepContext};
+use super::{Outcome, PostAction, Retry, StepContext};
 use crate::utils::{IntoDiagnosticShorthand, XTug};
 
 #[derive(Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Debug)]
@@ -19,11 +19,12 @@ pub struct NetworkAction {
     pub resolved: ResolvedNetworkRef,
 }
 
-pub async fn execute(ctx: &StepContext, action: NetworkAction) -> miette::Result<()> {
+pub async fn execute(ctx: &StepContext, action: NetworkAction) -> miette::Result<Outcome> {
     let remote_networks = remote_network_query(&ctx.service, ctx.group.clone(), action.name.clone()).await?;
 
     if remote_networks.is_empty() {
-        return create_network(ctx, action).await;
+        create_network(ctx, action).await?;
+        return Ok(Outcome::Created);
     }
 
     let first_network = remote_networks.first().unwrap();
@@ -36,7 +37,7 @@ pub async fn execute(ctx: &StepContext, action: NetworkAction) -> miette::Result
         ctx.resolved_networks
             .lock()
             .insert(action.resolved, first_network.name.as_ref().unwrap().clone());
-        return Ok(());
+        return Ok(Outcome::Unchanged);
     }
 
     for network in remote_networks {
@@ -45,7 +46,8 @@ pub async fn execute(ctx: &StepContext, action: NetworkAction) -> miette::Result
             .push(PostAction::DeleteNetwork { id: network.id.unwrap() });
     }
 
-    create_network(ctx, action).await
+    create_network(ctx, action).await?;
+    Ok(Outcome::Recreated)
 }
 
 async fn create_network(ctx: &StepContext, action: NetworkAction) -> miette::Result<()> {
@@ -72,16 +74,12 @@ async fn create_network(ctx: &StepContext, action: NetworkAction) -> miette::Res
 }
 
 pub async fn remote_network_query(service: &Podman, group: String, name: String) -> miette::Result<Vec<Network>> {
-    service
-        .networks()
-        .list(
-            &NetworkListOpts::builder()
-                .filter([
-                    NetworkListFilter::LabelKeyVal(XTug::Group.to_string(), group.clone()),
-                    NetworkListFilter::LabelKeyVal(XTug::Name.to_string(), name),
-                ])
-                .build(),
-        )
-        .await
-        .d()
+    let opts = NetworkListOpts::builder()
+        .filter([
+            NetworkListFilter::LabelKeyVal(XTug::Group.to_string(), group.clone()),
+            NetworkListFilter::LabelKeyVal(XTug::Name.to_string(), name),
+        ])
+        .build();
+    let networks = service.networks();
+    Retry::QUERY.run(|| networks.list(&opts)).await.d()
 }
@@ -0,0 +1,151 @@
+use std::collections::BTreeMap;
+
+use base64::{prelude::BASE64_URL_SAFE_NO_PAD, Engine};
+use hyper::{Body, Method};
+use podman_api::{
+    models::{IdResponse, ListPodsReport, Namespace, PortMapping},
+    opts::{PodCreateOpts, PodListFilter, PodListOpts},
+    Podman,
+};
+use serde::Serialize;
+
+use super::{
+    container::{ContainerActionNetwork, ContainerActionPort},
+    Outcome, PostAction, Retry, StepContext,
+};
+use crate::utils::{IntoDiagnosticShorthand, XTug};
+
+#[derive(Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Debug)]
+pub struct ResolvedPodRef(pub usize);
+
+#[derive(Clone, Debug)]
+pub struct PodAction {
+    pub name: String,
+    pub ports: Vec<ContainerActionPort>,
+    pub networks: Vec<ContainerActionNetwork>,
+    pub resolved: ResolvedPodRef,
+}
+
+// pods can't be changed after creation, so everything we configure goes into a
+// label and any difference recreates
+#[derive(Serialize)]
+struct PodFingerprint {
+    #[serde(rename = "p")]
+    ports: Vec<(u16, u16, &'static str)>,
+    #[serde(rename = "n")]
+    networks: BTreeMap<String, Vec<String>>,
+}
+
+pub async fn execute(ctx: &StepContext, action: PodAction) -> miette::Result<Outcome> {
+    let networks = action
+        .networks
+        .iter()
+        .map(|network| {
+            let mut aliases = network.aliases.clone();
+            aliases.sort_unstable();
+            (ctx.resolved_networks.lock()[&network.resolved].clone(), aliases)
+        })
+        .collect::<BTreeMap<_, _>>();
+    let mut ports = action
+        .ports
+        .iter()
+        .map(|port| (port.container, port.host, port.protocol.as_str()))
+        .collect::<Vec<_>>();
+    ports.sort_unstable();
+    let fingerprint = BASE64_URL_SAFE_NO_PAD.encode(rmp_serde::to_vec(&PodFingerprint { ports, networks }).d()?);
+
+    let remote_pods = remote_pod_query(&ctx.service, ctx.group.clone(), action.name.clone()).await?;
+
+    if let [pod] = remote_pods.as_slice() {
+        if pod
+            .labels
+            .as_ref()
+            .and_then(|labels| labels.get(XTug::PodFingerprint.as_ref()))
+            == Some(&fingerprint)
+        {
+            ctx.resolved_pods.lock().insert(action.resolved, pod.id.clone().unwrap());
+            return Ok(Outcome::Unchanged);
+        }
+    }
+
+    let outcome = if remote_pods.is_empty() {
+        Outcome::Created
+    } else {
+        Outcome::Recreated
+    };
+
+    // the old pod holds on to its ports until it's stopped
+    for pod in remote_pods {
+        let id = pod.id.unwrap();
+        if matches!(pod.status.as_deref(), Some("Running" | "Degraded")) {
+            ctx.service.pods().get(&id).stop().await.d()?;
+            ctx.backtrack.lock().push(PostAction::RestartPod { id: id.clone() });
+        }
+        ctx.finalize.lock().push(PostAction::DeletePod { id });
+    }
+
+    create_pod(ctx, action, fingerprint).await?;
+    Ok(outcome)
+}
+
+async fn create_pod(ctx: &StepContext, action: PodAction, fingerprint: String) -> miette::Result<()> {
+    let opts = PodCreateOpts::builder()
+        .labels([
+            (XTug::Group.to_string(), ctx.group.clone()),
+            (XTug::Name.to_string(), action.name.clone()),
+            (XTug::PodFingerprint.to_string(), fingerprint),
+        ])
+        .portmappings(action.ports.iter().map(|port| PortMapping {
+            container_port: Some(port.container),
+            host_ip: None,
+            host_port: Some(port.host),
+            protocol: Some(port.protocol.to_string()),
+            range: None,
+        }))
+        .netns(Namespace {
+            nsmode: Some("bridge".to_string()),
+            value: None,
+        })
+        .build();
+
+    // podman-api only knows about the deprecated cni_networks field, which can't
+    // carry aliases
+    let mut spec: serde_json::Value = serde_json::from_str(&opts.serialize().d()?).d()?;
+    spec["Networks"] = action
+        .networks
+        .iter()
+        .map(|network| {
+            (
+                ctx.resolved_networks.lock()[&network.resolved].clone(),
+                serde_json::json!({ "aliases": network.aliases }),
+            )
+        })
+        .collect::<serde_json::Map<_, _>>()
+        .into();
+
+    let response = ctx
+        .raw_service
+        .request(
+            Method::POST,
+            "/libpod/pods/create",
+            Body::from(serde_json::to_vec(&spec).d()?),
+        )
+        .await?;
+    let id = serde_json::from_slice::<IdResponse>(&response).d()?.id;
+
+    ctx.backtrack.lock().push(PostAction::DeletePod { id: id.clone() });
+    ctx.resolved_pods.lock().insert(action.resolved, id);
+
+    Ok(())
+}
+
+pub async fn remote_pod_query(service: &Podman, group: String, name: String) -> miette::Result<Vec<ListPodsReport>> {
+    let opts = PodListOpts::builder()
+        .filter([
+            PodListFilter::LabelKeyVal(XTug::Group.to_string(), group),
+            PodListFilter::LabelKeyVal(XTug::Name.to_string(), name),
+        ])
+        .build();
+    let pods = service.pods();
+    Retry::QUERY.run(|| pods.list(&opts)).await.d()
+}
@@ -0,0 +1,178 @@
+use std::{collections::BTreeMap, fmt::Display};
+
+use serde::Serialize;
+
+use super::{Action, Outcome, Step, StepStatus};
+
+#[derive(Default, Serialize)]
+pub struct Report {
+    pub succeeded: bool,
+    pub steps: Vec<StepReport>,
+    // the image id every image name resolved to
+    #[serde(skip)]
+    pub images: BTreeMap<String, String>,
+}
+
+#[derive(Serialize)]
+pub struct StepReport {
+    pub kind: &'static str,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub name: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub replica: Option<u32>,
+    pub outcome: Outcome,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub reason: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub duration_ms: Option<u128>,
+}
+
+impl StepReport {
+    pub fn new(step: &Step) -> StepReport {
+        let (outcome, reason) = match &step.status {
+            StepStatus::Succeeded(outcome) => (*outcome, None),
+            StepStatus::Failed(reason) => (Outcome::Failed, Some(reason.clone())),
+            StepStatus::Skipped(reason) => (Outcome::Skipped, Some(reason.clone())),
+            StepStatus::RolledBack(outcome) => (Outcome::RolledBack, Some(format!("was {outcome}"))),
+            // the run was cut short before this step got a chance
+            StepStatus::Queued | StepStatus::Running => (Outcome::Skipped, Some("never started".to_string())),
+        };
+
+        StepReport {
+            kind: step.action.kind(),
+            name: step.action.name().map(str::to_string),
+            replica: match &step.action {
+                Action::Container(action) if action.replica > 0 => Some(action.replica),
+                _ => None,
+            },
+            outcome,
+            reason,
+            duration_ms: step.duration.map(|duration| duration.as_millis()),
+        }
+    }
+}
+
+impl Display for Report {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let rows = self
+            .steps
+            .iter()
+            .map(|step| {
+                let name = match (&step.name, step.replica) {
+                    (Some(name), Some(replica)) => format!("{name} (replica {replica})"),
+                    (Some(name), None) => name.clone(),
+                    (None, _) => "-".to_string(),
+                };
+                let duration = match step.duration_ms {
+                    Some(ms) => format!("{:.1}s", ms as f64 / 1000.0),
+                    None => "-".to_string(),
+                };
+                [
+                    step.kind.to_string(),
+                    name,
+                    step.outcome.to_string(),
+                    duration,
+                    step.reason.clone().unwrap_or_default(),
+                ]
+            })
+            .collect::<Vec<_>>();
+
+        let header = ["KIND", "NAME", "OUTCOME", "DURATION", "REASON"].map(str::to_string);
+        let mut widths = header.clone().map(|column| column.len());
+        for row in &rows {
+            for (width, column) in widths.iter_mut().zip(row) {
+                *width = (*width).max(column.len());
+            }
+        }
+
+        for row in std::iter::once(&header).chain(&rows) {
+            let mut line = String::new();
+            for (column, width) in row.iter().zip(widths) {
+                line.push_str(&format!("{column:width$}  "));
+            }
+            writeln!(f, "{}", line.trim_end())?;
+        }
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn report() -> Report {
+        Report {
+            succeeded: false,
+            steps: vec![
+                StepReport {
+                    kind: "container",
+                    name: Some("web".to_string()),
+                    replica: Some(2),
+                    outcome: Outcome::Recreated,
+                    reason: None,
+                    duration_ms: Some(1300),
+                },
+                StepReport {
+                    kind: "network",
+                    name: Some("default".to_string()),
+                    replica: None,
+                    outcome: Outcome::RolledBack,
+                    reason: Some("was created".to_string()),
+                    duration_ms: Some(200),
+                },
+                StepReport {
+                    kind: "garbage",
+                    name: None,
+                    replica: None,
+                    outcome: Outcome::Skipped,
+                    reason: Some("never started".to_string()),
+                    duration_ms: None,
+                },
+            ],
+            images: BTreeMap::from([("web".to_string(), "abc".to_string())]),
+        }
+    }
+
+    #[test]
+    fn table() {
+        assert_eq!(
+            report().to_string(),
+            "KIND       NAME             OUTCOME      DURATION  REASON\n\
+             container  web (replica 2)  recreated    1.3s\n\
+             network    default          rolled back  0.2s      was created\n\
+             garbage    -                skipped      -         never started\n"
+        );
+    }
+
+    #[test]
+    fn json() {
+        assert_eq!(
+            serde_json::to_value(report()).unwrap(),
+            serde_json::json!({
+                "succeeded": false,
+                "steps": [
+                    {
+                        "kind": "container",
+                        "name": "web",
+                        "replica": 2,
+                        "outcome": "recreated",
+                        "duration_ms": 1300,
+                    },
+                    {
+                        "kind": "network",
+                        "name": "default",
+                        "outcome": "rolled-back",
+                        "reason": "was created",
+                        "duration_ms": 200,
+                    },
+                    {
+                        "kind": "garbage",
+                        "outcome": "skipped",
+                        "reason": "never started",
+                    },
+                ],
+            })
+        );
+    }
+}
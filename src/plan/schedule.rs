@@ -0,0 +1,230 @@
+use std::{io, path::PathBuf};
+
+use podman_api::{
+    models::ListContainer,
+    opts::{ContainerListFilter, ContainerListOpts},
+    Podman,
+};
+
+use super::{
+    job::{create_job_container, fingerprint_matches, job_fingerprint, JobAction},
+    Outcome, PostAction, Retry, StepContext,
+};
+use crate::utils::{IntoDiagnosticShorthand, XTug};
+
+#[derive(Clone, Debug)]
+pub struct ScheduleAction {
+    pub job: JobAction,
+    pub on_calendar: Vec<String>,
+}
+
+#[derive(Clone, PartialEq, Eq, Debug)]
+pub struct Units {
+    pub service: String,
+    pub timer: String,
+}
+
+pub async fn execute(ctx: &StepContext, action: ScheduleAction) -> miette::Result<Outcome> {
+    // the timers run wherever tug does, so they'd fire on the wrong machine for a
+    // remote service
+    if !ctx.raw_service.uri().starts_with("unix://") {
+        miette::bail!(
+            help = "point tug at the podman socket on the machine the schedule should run on",
+            "schedule `{}` needs a local podman service",
+            action.job.name
+        );
+    }
+
+    let (fingerprint, secret_fulls) = job_fingerprint(ctx, &action.job).await?;
+
+    let remote_containers = remote_schedule_query(&ctx.service, ctx.group.clone(), action.job.name.clone()).await?;
+    let existing = remote_containers
+        .iter()
+        .find(|container| fingerprint_matches(container, &fingerprint))
+        .and_then(|container| container.id.clone());
+    let mut outcome = match (&existing, remote_containers.is_empty()) {
+        (Some(_), _) => Outcome::Unchanged,
+        (None, true) => Outcome::Created,
+        (None, false) => Outcome::Recreated,
+    };
+    let id = match existing {
+        Some(id) => id,
+        None => create_job_container(ctx, &action.job, &fingerprint, &secret_fulls, XTug::Schedule).await?,
+    };
+    for container in remote_containers {
+        let old = container.id.unwrap();
+        if old != id {
+            ctx.finalize.lock().push(PostAction::DeleteContainer { id: old });
+        }
+    }
+
+    let unit = unit_name(&ctx.group, &action.job.name);
+    let header = format!(
+        "[Unit]\nDescription=tug schedule {name} in group {group}\n{}={group}\n{}={name}\n",
+        XTug::Group.as_ref(),
+        XTug::Schedule.as_ref(),
+        name = action.job.name,
+        group = ctx.group,
+    );
+    let units = Units {
+        service: format!(
+            "{header}\n[Service]\nType=oneshot\nExecStart=podman --url {} start --attach {id}\n",
+            ctx.raw_service.uri()
+        ),
+        timer: format!(
+            "{header}\n[Timer]\n{}Persistent=true\n\n[Install]\nWantedBy=timers.target\n",
+            action
+                .on_calendar
+                .iter()
+                .map(|calendar| format!("OnCalendar={calendar}\n"))
+                .collect::<String>()
+        ),
+    };
+
+    let previous = read_units(&unit).await?;
+    if previous.as_ref() != Some(&units) {
+        if outcome == Outcome::Unchanged {
+            outcome = Outcome::Recreated;
+        }
+        write_units(&unit, Some(&units)).await.d()?;
+        ctx.backtrack
+            .lock()
+            .push(PostAction::RestoreSchedule { unit, units: previous });
+    }
+
+    Ok(outcome)
+}
+
+pub async fn remote_schedule_query(service: &Podman, group: String, name: String) -> miette::Result<Vec<ListContainer>> {
+    let opts = ContainerListOpts::builder()
+        .all(true)
+        .filter([
+            ContainerListFilter::LabelKeyVal(XTug::Group.to_string(), group),
+            ContainerListFilter::LabelKeyVal(XTug::Schedule.to_string(), name),
+        ])
+        .build();
+    let containers = service.containers();
+    Retry::QUERY.run(|| containers.list(&opts)).await.d()
+}
+
+#[derive(miette::Diagnostic, thiserror::Error, Debug)]
+#[error("can't find a config directory to keep systemd units in")]
+#[diagnostic(help("set HOME or XDG_CONFIG_HOME so schedules can go in systemd/user under it"))]
+struct NoUnitDirectory;
+
+fn unit_directory() -> Result<PathBuf, NoUnitDirectory> {
+    Ok(dirs::config_dir().ok_or(NoUnitDirectory)?.join("systemd/user"))
+}
+
+fn unit_name(group: &str, name: &str) -> String {
+    format!("tug-{group}-{name}")
+        .chars()
+        .map(|c| {
+            if c.is_ascii_alphanumeric() || "-_.".contains(c) {
+                c
+            } else {
+                '_'
+            }
+        })
+        .collect()
+}
+
+// lists the units tug wrote for a group, going by the keys in their headers
+// rather than the file names
+pub async fn local_schedule_units(group: &str) -> miette::Result<Vec<(String, String)>> {
+    let mut entries = match tokio::fs::read_dir(unit_directory()?).await {
+        Ok(entries) => entries,
+        Err(err) if err.kind() == io::ErrorKind::NotFound => return Ok(Vec::new()),
+        Err(err) => return Err(err).d(),
+    };
+
+    let mut units = Vec::new();
+    while let Some(entry) = entries.next_entry().await.d()? {
+        let file_name = entry.file_name().to_string_lossy().into_owned();
+        let Some(unit) = file_name.strip_suffix(".timer").filter(|unit| unit.starts_with("tug-")) else {
+            continue;
+        };
+        let content = tokio::fs::read_to_string(entry.path()).await.d()?;
+        let value = |key: XTug| {
+            content
+                .lines()
+                .find_map(|line| line.strip_prefix(key.as_ref())?.strip_prefix('='))
+                .map(str::to_string)
+        };
+        if value(XTug::Group).as_deref() == Some(group) {
+            if let Some(name) = value(XTug::Schedule) {
+                units.push((unit.to_string(), name));
+            }
+        }
+    }
+
+    Ok(units)
+}
+
+async fn read_units(unit: &str) -> miette::Result<Option<Units>> {
+    let directory = unit_directory()?;
+    let read = |extension| {
+        let path = directory.join(format!("{unit}.{extension}"));
+        async move {
+            match tokio::fs::read_to_string(path).await {
+                Ok(content) => Ok(Some(content)),
+                Err(err) if err.kind() == io::ErrorKind::NotFound => Ok(None),
+                Err(err) => Err(err),
+            }
+        }
+    };
+
+    Ok(match (read("service").await.d()?, read("timer").await.d()?) {
+        (Some(service), Some(timer)) => Some(Units { service, timer }),
+        _ => None,
+    })
+}
+
+// only runs after the units were read, so the directory lookup can't fail here
+// in practice
+pub async fn write_units(unit: &str, units: Option<&Units>) -> io::Result<()> {
+    let directory = unit_directory().map_err(|err| io::Error::new(io::ErrorKind::NotFound, err))?;
+    let timer = format!("{unit}.timer");
+
+    match units {
+        Some(units) => {
+            tokio::fs::create_dir_all(&directory).await?;
+            tokio::fs::write(directory.join(format!("{unit}.service")), &units.service).await?;
+            tokio::fs::write(directory.join(&timer), &units.timer).await?;
+            systemctl(&["daemon-reload"]).await?;
+            systemctl(&["enable", &timer]).await?;
+            systemctl(&["restart", &timer]).await?;
+        }
+        None => {
+            if tokio::fs::try_exists(directory.join(&timer)).await? {
+                systemctl(&["disable", "--now", &timer]).await?;
+            }
+            for extension in ["service", "timer"] {
+                match tokio::fs::remove_file(directory.join(format!("{unit}.{extension}"))).await {
+                    Err(err) if err.kind() != io::ErrorKind::NotFound => return Err(err),
+                    _ => {}
+                }
+            }
+            systemctl(&["daemon-reload"]).await?;
+        }
+    }
+
+    Ok(())
+}
+
+async fn systemctl(args: &[&str]) -> io::Result<()> {
+    let status = tokio::process::Command::new("systemctl")
+        .arg("--user")
+        .args(args)
+        .stdin(std::process::Stdio::null())
+        .status()
+        .await?;
+    if !status.success() {
+        return Err(io::Error::new(
+            io::ErrorKind::Other,
+            format!("`systemctl --user {}` exited with {status}", args.join(" ")),
+        ));
+    }
+
+    Ok(())
+}
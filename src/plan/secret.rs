@@ -1,7 +1,12 @@
+use std::{collections::HashMap, path::PathBuf};
+
+use base64::{prelude::BASE64_URL_SAFE_NO_PAD, Engine};
+use hyper::{Body, Method};
 use knuffel::span::Spanned;
-use miette::{NamedSource, SourceSpan};
+use miette::{Context, NamedSource, SourceSpan};
+use sha2::{Digest, Sha256};
 
-use super::StepContext;
+use super::{Outcome, PostAction, Retry, StepContext};
 use crate::{
     parse::span::ParseSpan,
     utils::{IntoDiagnosticShorthand, XTug},
@@ -11,15 +16,25 @@ use crate::{
 pub struct SecretAction {
     pub resolved: ResolvedSecretRef,
     pub name: Spanned<String, ParseSpan>,
+    pub source: Option<SecretSource>,
+}
+
+#[derive(Clone, Debug)]
+pub enum SecretSource {
+    File(PathBuf),
+    Env(String),
+    Command(Vec<String>),
+    Age(PathBuf),
 }
 
 #[derive(Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Debug)]
 pub struct ResolvedSecretRef(pub usize);
 
-pub async fn execute(ctx: &StepContext, action: SecretAction) -> miette::Result<()> {
+pub async fn execute(ctx: &StepContext, action: SecretAction) -> miette::Result<Outcome> {
     let name = action.name.to_string();
 
-    let secrets = ctx.service.secrets().list().await.d()?;
+    let secrets = ctx.service.secrets();
+    let secrets = Retry::QUERY.run(|| secrets.list()).await.d()?;
     let secrets = secrets
         .into_iter()
         .map(|report| {
@@ -33,26 +48,141 @@ pub async fn execute(ctx: &StepContext, action: SecretAction) -> miette::Result<
         })
         .collect::<Vec<_>>();
 
-    if let Some((id, _)) = secrets.iter().find(|(_, secret)| {
-        secret.1.get(XTug::Group.as_ref()) == Some(&ctx.group) && secret.1.get(XTug::Name.as_ref()) == Some(&name)
-    }) {
-        ctx.resolved_secrets.lock().insert(action.resolved, id.clone());
-        return Ok(());
+    if let Some(source) = &action.source {
+        let content = read_source(ctx, source)
+            .await
+            .wrap_err_with(|| format!("reading source for secret `{name}`"))?;
+        let mut resolved = None;
+        let mut replaced = false;
+        for (id, (_, labels)) in &secrets {
+            if labels.get(XTug::Group.as_ref()) != Some(&ctx.group) || labels.get(XTug::Name.as_ref()) != Some(&name) {
+                continue;
+            }
+            let matches = labels
+                .get(XTug::SecretDigest.as_ref())
+                .and_then(|label| label.split_once('.'))
+                .and_then(|(salt, _)| BASE64_URL_SAFE_NO_PAD.decode(salt).ok())
+                .map(|salt| Some(&salted_digest(&salt, &content)) == labels.get(XTug::SecretDigest.as_ref()))
+                .unwrap_or(false);
+            if resolved.is_none() && matches {
+                resolved = Some(id.clone());
+            } else {
+                ctx.finalize.lock().push(PostAction::DeleteSecret { id: id.clone() });
+                replaced = true;
+            }
+        }
+
+        let (id, outcome) = match resolved {
+            Some(id) => (id, Outcome::Unchanged),
+            None => {
+                let digest = salted_digest(&secret_salt(&ctx.group, &name), &content);
+                let id = create_secret(ctx, &name, &digest, content).await?;
+                ctx.backtrack.lock().push(PostAction::DeleteSecret { id: id.clone() });
+                (id, if replaced { Outcome::Recreated } else { Outcome::Created })
+            }
+        };
+        ctx.resolved_secrets.lock().insert(action.resolved, id);
+        return Ok(outcome);
     }
 
+    // secrets tug made for a `secret` node that's gone get deleted by the garbage
+    // step, so only ones made outside of tug count here
     if let Some((id, _)) = secrets.iter().find(|(_, secret)| secret.0 == name) {
         ctx.resolved_secrets.lock().insert(action.resolved, id.clone());
-        return Ok(());
+        return Ok(Outcome::Unchanged);
     }
 
     Err(SecretNotFound {
         name,
         content: crate::prepare::diagnostics::read_source(action.name.span())?,
         reference: action.name.span().source_span(),
-        help: "you can create secrets with `podman secret create`",
+        help: "you can declare secrets with a `secret` node, or create them with `podman secret create`",
     })?
 }
 
+async fn read_source(ctx: &StepContext, source: &SecretSource) -> miette::Result<Vec<u8>> {
+    match source {
+        SecretSource::File(path) => tokio::fs::read(ctx.root_directory.join(path)).await.d(),
+        SecretSource::Env(variable) => match std::env::var_os(variable) {
+            Some(value) => Ok(crate::utils::os_string_vec(value)),
+            None => Err(miette::miette!("environment variable `{variable}` is not set")),
+        },
+        SecretSource::Command(command) => {
+            let output = tokio::process::Command::new(&command[0])
+                .args(&command[1..])
+                .current_dir(&ctx.root_directory)
+                .stdin(std::process::Stdio::null())
+                .stderr(std::process::Stdio::inherit())
+                .output()
+                .await
+                .d()
+                .wrap_err_with(|| format!("running `{}`", command[0]))?;
+            if !output.status.success() {
+                return Err(miette::miette!("`{}` exited with {}", command[0], output.status));
+            }
+            Ok(output.stdout)
+        }
+        SecretSource::Age(path) => {
+            let ciphertext = tokio::fs::read(ctx.root_directory.join(path)).await.d()?;
+            let identity = match &ctx.identity {
+                Some(identity) => identity.clone(),
+                None => crate::config::default_identity()?,
+            };
+            crate::encryption::decrypt(&identity, &ciphertext).wrap_err_with(|| format!("decrypting {path:?}"))
+        }
+    }
+}
+
+// the salt keeps the label from being compared against precomputed digests of
+// low-entropy secrets. it's derived from the secret's identity rather than random
+// so the same content always ends up with the same digest, and consumers don't
+// get restarted just because a secret was recreated
+fn secret_salt(group: &str, name: &str) -> [u8; 16] {
+    let digest = Sha256::new()
+        .chain_update(b"tug-secret-salt\0")
+        .chain_update(group)
+        .chain_update(b"\0")
+        .chain_update(name)
+        .finalize();
+    let mut salt = [0; 16];
+    salt.copy_from_slice(&digest[..16]);
+    salt
+}
+
+// stored alongside the digest as `<salt>.<digest>` so the comparison can be
+// repeated, even for secrets created with an older salt
+fn salted_digest(salt: &[u8], content: &[u8]) -> String {
+    let digest = Sha256::new().chain_update(salt).chain_update(content).finalize();
+    format!(
+        "{}.{}",
+        BASE64_URL_SAFE_NO_PAD.encode(salt),
+        BASE64_URL_SAFE_NO_PAD.encode(digest)
+    )
+}
+
+async fn create_secret(ctx: &StepContext, name: &str, digest: &str, content: Vec<u8>) -> miette::Result<String> {
+    let labels = HashMap::from([
+        (XTug::Group.as_ref(), ctx.group.as_str()),
+        (XTug::Name.as_ref(), name),
+        (XTug::SecretDigest.as_ref(), digest),
+    ]);
+    let query = url::form_urlencoded::Serializer::new(String::new())
+        .append_pair("name", &format!("tug-{}", digest.replace('.', "-")))
+        .append_pair("labels", &serde_json::to_string(&labels).d()?)
+        .finish();
+
+    let response = ctx
+        .raw_service
+        .request(Method::POST, &format!("/libpod/secrets/create?{query}"), Body::from(content))
+        .await
+        .wrap_err_with(|| format!("creating secret `{name}`"))?;
+
+    serde_json::from_slice::<podman_api::models::SecretCreateResponse>(&response)
+        .d()?
+        .id
+        .ok_or_else(|| miette::miette!("secret creation response missing id"))
+}
+
 #[derive(miette::Diagnostic, thiserror::Error, Debug)]
 #[error("secret `{name}` not found")]
 struct SecretNotFound {
@@ -4,7 +4,7 @@ use podman_api::{
     Podman,
 };
 
-use super::{PostAction, StepContext};
+use super::{Outcome, PostAction, Retry, StepContext};
 use crate::utils::{IntoDiagnosticShorthand, XTug};
 
 #[derive(Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Debug)]
@@ -17,40 +17,38 @@ pub struct VolumeAction {
     pub resolved: ResolvedVolumeRef,
 }
 
-pub async fn execute(ctx: &StepContext, action: VolumeAction) -> miette::Result<()> {
+pub async fn execute(ctx: &StepContext, action: VolumeAction) -> miette::Result<Outcome> {
     let remote_volumes = remote_volume_query(&ctx.service, ctx.group.clone(), action.name.clone()).await?;
 
     if remote_volumes.is_empty() {
-        return create_volume(ctx, action).await;
+        create_volume(ctx, action).await?;
+        return Ok(Outcome::Created);
     }
 
     let first_volume = remote_volumes.first().unwrap();
 
     if remote_volumes.len() == 1 && first_volume.driver == action.driver {
         ctx.resolved_volumes.lock().insert(action.resolved, first_volume.name.clone());
-        return Ok(());
+        return Ok(Outcome::Unchanged);
     }
 
     for volume in remote_volumes {
         ctx.finalize.lock().push(PostAction::DeleteVolume { name: volume.name });
     }
 
-    create_volume(ctx, action).await
+    create_volume(ctx, action).await?;
+    Ok(Outcome::Recreated)
 }
 
 pub async fn remote_volume_query(service: &Podman, group: String, name: String) -> miette::Result<Vec<Volume>> {
-    service
-        .volumes()
-        .list(
-            &VolumeListOpts::builder()
-                .filter([
-                    VolumeListFilter::LabelKeyVal(XTug::Group.to_string(), group.clone()),
-                    VolumeListFilter::LabelKeyVal(XTug::Name.to_string(), name),
-                ])
-                .build(),
-        )
-        .await
-        .d()
+    let opts = VolumeListOpts::builder()
+        .filter([
+            VolumeListFilter::LabelKeyVal(XTug::Group.to_string(), group.clone()),
+            VolumeListFilter::LabelKeyVal(XTug::Name.to_string(), name),
+        ])
+        .build();
+    let volumes = service.volumes();
+    Retry::QUERY.run(|| volumes.list(&opts)).await.d()
 }
 
 async fn create_volume(ctx: &StepContext, action: VolumeAction) -> miette::Result<()> {
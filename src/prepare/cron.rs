@@ -0,0 +1,137 @@
+// translates cron expressions into systemd OnCalendar expressions
+
+const MONTHS: [&str; 12] = [
+    "jan", "feb", "mar", "apr", "may", "jun", "jul", "aug", "sep", "oct", "nov", "dec",
+];
+const WEEKDAYS: [&str; 7] = ["Sun", "Mon", "Tue", "Wed", "Thu", "Fri", "Sat"];
+
+pub fn to_on_calendar(cron: &str) -> Option<Vec<String>> {
+    if let Some(nickname) = cron.trim().strip_prefix('@') {
+        let calendar = match nickname {
+            "yearly" | "annually" => "yearly",
+            "monthly" => "monthly",
+            "weekly" => "weekly",
+            "daily" | "midnight" => "daily",
+            "hourly" => "hourly",
+            _ => return None,
+        };
+        return Some(vec![calendar.to_string()]);
+    }
+
+    let [minute, hour, day, month, weekday] = cron.split_whitespace().collect::<Vec<_>>()[..] else {
+        return None;
+    };
+    let minute = field(minute, 0, 59, &[])?;
+    let hour = field(hour, 0, 23, &[])?;
+    let day = field(day, 1, 31, &[])?;
+    let month = field(month, 1, 12, &MONTHS)?;
+    let weekday = field(weekday, 0, 7, &WEEKDAYS)?.map(|days| {
+        let mut days = days.into_iter().map(|day| day % 7).collect::<Vec<_>>();
+        days.sort_unstable();
+        days.dedup();
+        days.into_iter()
+            .map(|day| WEEKDAYS[day as usize])
+            .collect::<Vec<_>>()
+            .join(",")
+    });
+
+    let list = |values: Option<Vec<u32>>| match values {
+        Some(values) => values.iter().map(u32::to_string).collect::<Vec<_>>().join(","),
+        None => "*".to_string(),
+    };
+    let (minute, hour, month) = (list(minute), list(hour), list(month));
+
+    // cron runs when either the day of the month or the weekday matches if both
+    // are restricted, but systemd wants both to match, so that needs two timers
+    Some(match (day, weekday) {
+        (Some(day), Some(weekday)) => vec![
+            format!("*-{month}-{} {hour}:{minute}:00", list(Some(day))),
+            format!("{weekday} *-{month}-* {hour}:{minute}:00"),
+        ],
+        (day, Some(weekday)) => vec![format!("{weekday} *-{month}-{} {hour}:{minute}:00", list(day))],
+        (day, None) => vec![format!("*-{month}-{} {hour}:{minute}:00", list(day))],
+    })
+}
+
+// expands a field into the values it matches, or None for `*`
+fn field(field: &str, min: u32, max: u32, names: &[&str]) -> Option<Option<Vec<u32>>> {
+    if field == "*" {
+        return Some(None);
+    }
+
+    let value = |value: &str| {
+        let parsed = match names.iter().position(|name| name.eq_ignore_ascii_case(value)) {
+            // month names start at 1, weekday names at 0
+            Some(index) => index as u32 + min,
+            None => value.parse().ok()?,
+        };
+        (min..=max).contains(&parsed).then_some(parsed)
+    };
+
+    let mut values = Vec::new();
+    for part in field.split(',') {
+        let (range, step) = match part.split_once('/') {
+            Some((range, step)) => (range, step.parse::<usize>().ok().filter(|step| *step > 0)?),
+            None => (part, 1),
+        };
+        let (start, end) = match range.split_once('-') {
+            _ if range == "*" => (min, max),
+            Some((start, end)) => (value(start)?, value(end)?),
+            None if step > 1 => (value(range)?, max),
+            None => (value(range)?, value(range)?),
+        };
+        if start > end {
+            return None;
+        }
+        values.extend((start..=end).step_by(step));
+    }
+    values.sort_unstable();
+    values.dedup();
+
+    Some(Some(values))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::to_on_calendar as calendar;
+
+    #[test]
+    fn nicknames() {
+        assert_eq!(calendar("@daily"), Some(vec!["daily".to_string()]));
+        assert_eq!(calendar("@annually"), Some(vec!["yearly".to_string()]));
+        assert_eq!(calendar("@reboot"), None);
+    }
+
+    #[test]
+    fn fields() {
+        assert_eq!(calendar("*/15 * * * *"), Some(vec!["*-*-* *:0,15,30,45:00".to_string()]));
+        assert_eq!(calendar("30 4 1-3 jan *"), Some(vec!["*-1-1,2,3 4:30:00".to_string()]));
+        assert_eq!(
+            calendar("0 9 * * mon-fri"),
+            Some(vec!["Mon,Tue,Wed,Thu,Fri *-*-* 9:0:00".to_string()])
+        );
+    }
+
+    #[test]
+    fn sunday_is_0_and_7() {
+        assert_eq!(calendar("0 0 * * 0,7"), Some(vec!["Sun *-*-* 0:0:00".to_string()]));
+    }
+
+    // cron's either-or gets split into two timers
+    #[test]
+    fn day_and_weekday() {
+        assert_eq!(
+            calendar("0 12 13 * fri"),
+            Some(vec!["*-*-13 12:0:00".to_string(), "Fri *-*-* 12:0:00".to_string()])
+        );
+    }
+
+    #[test]
+    fn malformed() {
+        assert_eq!(calendar("* * * *"), None);
+        assert_eq!(calendar("60 * * * *"), None);
+        assert_eq!(calendar("5-1 * * * *"), None);
+        assert_eq!(calendar("*/0 * * * *"), None);
+        assert_eq!(calendar("0 0 * smarch *"), None);
+    }
+}
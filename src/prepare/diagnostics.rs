@@ -41,7 +41,7 @@ pub struct UnknownThing {
 }
 
 impl UnknownThing {
-    pub fn build(name_space: Spanned<String, ParseSpan>, what: &'static str) -> miette::Result<()> {
+    pub fn build<T>(name_space: Spanned<String, ParseSpan>, what: &'static str) -> miette::Result<T> {
         let span = name_space.span();
         let content = std::fs::read_to_string(&span.file).d()?;
 
@@ -64,6 +64,106 @@ pub struct DuplicateInjectPath {
     pub second: SourceSpan,
 }
 
+#[derive(thiserror::Error, Debug, Diagnostic)]
+#[error("malformed secret mode")]
+pub struct MalformedSecretMode {
+    #[source_code]
+    pub content: NamedSource,
+    #[label("defined here")]
+    pub here: SourceSpan,
+    #[help]
+    pub help: &'static str,
+}
+
+#[derive(thiserror::Error, Debug, Diagnostic)]
+#[error("mount options on environment secret")]
+pub struct EnvSecretMountOptions {
+    #[source_code]
+    pub content: NamedSource,
+    #[label("secret defined here")]
+    pub here: SourceSpan,
+    #[help]
+    pub help: &'static str,
+}
+
+#[derive(thiserror::Error, Debug, Diagnostic)]
+#[error("secret must have exactly one source")]
+pub struct BadSecretSource {
+    #[source_code]
+    pub content: NamedSource,
+    #[label("declared here")]
+    pub here: SourceSpan,
+    #[help]
+    pub help: &'static str,
+}
+
+#[derive(thiserror::Error, Debug, Diagnostic)]
+#[error("pod member has its own networking")]
+pub struct PodMemberNetworking {
+    #[source_code]
+    pub content: NamedSource,
+    #[label("pod referenced here")]
+    pub here: SourceSpan,
+    #[help]
+    pub help: &'static str,
+}
+
+#[derive(thiserror::Error, Debug, Diagnostic)]
+#[error("image {problem} tug.lock")]
+pub struct StaleLock {
+    #[source_code]
+    pub content: NamedSource,
+    #[label("reference defined here")]
+    pub here: SourceSpan,
+    pub problem: &'static str,
+    #[help]
+    pub help: &'static str,
+}
+
+#[derive(thiserror::Error, Debug, Diagnostic)]
+#[error("pull policy on local image")]
+pub struct LocalImagePull {
+    #[source_code]
+    pub content: NamedSource,
+    #[label("pull policy set here")]
+    pub here: SourceSpan,
+    #[help]
+    pub help: &'static str,
+}
+
+#[derive(thiserror::Error, Debug, Diagnostic)]
+#[error("replicated container binds host ports")]
+pub struct ReplicatedHostPorts {
+    #[source_code]
+    pub content: NamedSource,
+    #[label("replicas defined here")]
+    pub here: SourceSpan,
+    #[help]
+    pub help: &'static str,
+}
+
+#[derive(thiserror::Error, Debug, Diagnostic)]
+#[error("replicated container is in a pod")]
+pub struct ReplicatedPodMember {
+    #[source_code]
+    pub content: NamedSource,
+    #[label("replicas defined here")]
+    pub here: SourceSpan,
+    #[help]
+    pub help: &'static str,
+}
+
+#[derive(thiserror::Error, Debug, Diagnostic)]
+#[error("container has no replicas")]
+pub struct NoReplicas {
+    #[source_code]
+    pub content: NamedSource,
+    #[label("replicas defined here")]
+    pub here: SourceSpan,
+    #[help]
+    pub help: &'static str,
+}
+
 #[derive(thiserror::Error, Debug, Diagnostic)]
 #[error("malformed command")]
 pub struct MalformedCommand {
@@ -72,3 +172,34 @@ pub struct MalformedCommand {
     #[label("defined here")]
     pub here: SourceSpan,
 }
+
+#[derive(thiserror::Error, Debug, Diagnostic)]
+#[error("dependency cycle")]
+pub struct DependencyCycle {
+    #[source_code]
+    pub content: NamedSource,
+    #[label("cycles back here")]
+    pub here: SourceSpan,
+}
+
+#[derive(thiserror::Error, Debug, Diagnostic)]
+#[error("malformed cron expression")]
+pub struct MalformedCron {
+    #[source_code]
+    pub content: NamedSource,
+    #[label("defined here")]
+    pub here: SourceSpan,
+    #[help]
+    pub help: &'static str,
+}
+
+#[derive(thiserror::Error, Debug, Diagnostic)]
+#[error("malformed hook")]
+pub struct BadHook {
+    #[source_code]
+    pub content: NamedSource,
+    #[label("defined here")]
+    pub here: SourceSpan,
+    #[help]
+    pub help: &'static str,
+}
@@ -1,47 +1,100 @@
+mod cron;
 pub mod diagnostics;
 
 use std::collections::{BTreeSet, HashMap};
 
+use knuffel::span::Spanned;
 use miette::NamedSource;
 
-use self::diagnostics::{read_source, DuplicateInjectPath, DuplicateName, MalformedCommand, UnknownThing};
+use self::diagnostics::{
+    read_source, BadHook, BadSecretSource, DependencyCycle, DuplicateInjectPath, DuplicateName, EnvSecretMountOptions,
+    LocalImagePull, MalformedCommand, MalformedCron, MalformedSecretMode, NoReplicas, PodMemberNetworking, ReplicatedHostPorts,
+    ReplicatedPodMember, StaleLock, UnknownThing,
+};
 use crate::{
+    lock::{Lock, LOCK_FILE},
     logger::Logger,
-    parse::model::{ParsedContainerMount, ParsedContainerPort, ParsedDocument, ParsedExplicitContainerPort, ParsedProtocol},
+    parse::{
+        model::{
+            ParsedContainerInject, ParsedContainerMount, ParsedContainerNetwork, ParsedContainerPort, ParsedContainerSecret,
+            ParsedDocument, ParsedExplicitContainerPort, ParsedHookKind, ParsedProtocol, ParsedSecretType,
+        },
+        span::ParseSpan,
+    },
     plan::{
         container::{
             ContainerAction, ContainerActionBindMount, ContainerActionNetwork, ContainerActionPort, ContainerActionSecret,
-            ContainerActionVolumeMount,
+            ContainerActionSecretKind, ContainerActionVolumeMount,
         },
         garbage::GarbageAction,
         image::{ImageAction, ResolvedImageRef},
+        job::JobAction,
         network::{NetworkAction, ResolvedNetworkRef},
-        secret::{ResolvedSecretRef, SecretAction},
+        pod::{PodAction, ResolvedPodRef},
+        schedule::ScheduleAction,
+        secret::{ResolvedSecretRef, SecretAction, SecretSource},
         volume::{ResolvedVolumeRef, VolumeAction},
         Action, Executor,
     },
     utils::IntoDiagnosticShorthand,
 };
 
-pub fn prepare(logger: &Logger, document: ParsedDocument, executor: &mut Executor) -> miette::Result<()> {
-    logger.log("Preparing plan");
+pub fn prepare(
+    logger: &Logger,
+    document: ParsedDocument,
+    lock: Option<&Lock>,
+    locked: bool,
+    executor: &mut Executor,
+) -> miette::Result<()> {
+    logger.info("Preparing plan");
 
-    logger.log("Queueing garbage pass");
+    logger.debug("Queueing garbage pass");
     executor.new_step(
         Action::Garbage(GarbageAction {
-            container_names: document
+            container_replicas: document
                 .containers
                 .iter()
-                .map(|container| container.name.to_string())
+                .map(|container| (container.name.to_string(), replica_count(&container.replicas)))
                 .collect(),
+            secret_names: document.secrets.iter().map(|secret| secret.name.to_string()).collect(),
+            pod_names: document.pods.iter().map(|pod| pod.name.to_string()).collect(),
+            job_names: document.jobs.iter().map(|job| job.name.to_string()).collect(),
+            schedule_names: document.schedules.iter().map(|schedule| schedule.name.to_string()).collect(),
         }),
         BTreeSet::new(),
     );
 
-    logger.log("Queueing images");
+    logger.debug("Queueing images");
     let mut image_name_to_dependency = HashMap::new();
     let mut counter = 1;
     for image in document.images {
+        if let (true, Some(pull)) = (image.local, &image.pull) {
+            Err(LocalImagePull {
+                content: read_source(pull.span())?,
+                here: pull.span().source_span(),
+                help: "local images never get pulled, so drop either `pull` or `local=true`",
+            })?
+        }
+        let pinned = match lock {
+            // tug.lock never has local images, but a revision being rolled back to does
+            Some(lock) if image.local => lock.pin(&image.name, &image.reference).ok().map(str::to_string),
+            Some(lock) => match lock.pin(&image.name, &image.reference) {
+                Ok(digest) => Some(digest.to_string()),
+                Err(problem) => {
+                    if locked {
+                        Err(StaleLock {
+                            content: read_source(image.reference.span())?,
+                            here: image.reference.span().source_span(),
+                            problem,
+                            help: "run `tug lock` to update it",
+                        })?
+                    }
+                    logger.warn(format!("image `{}` {problem} {LOCK_FILE}, using it unpinned", *image.name));
+                    None
+                }
+            },
+            None => None,
+        };
         let resolved = ResolvedImageRef(counter);
         counter += 1;
         let step_id = executor.new_step(
@@ -51,6 +104,9 @@ pub fn prepare(logger: &Logger, document: ParsedDocument, executor: &mut Executo
                 reference: image.reference.to_string(),
                 reference_span: image.reference.span().clone(),
                 local: image.local,
+                pull: image.pull.map(|pull| *pull).unwrap_or_default(),
+                pinned,
+                pre_pulled: None,
             }),
             BTreeSet::new(),
         );
@@ -60,12 +116,25 @@ pub fn prepare(logger: &Logger, document: ParsedDocument, executor: &mut Executo
             DuplicateName::from_spans(&old_span, image.name.span())?
         }
     }
+    if let Some(lock) = lock {
+        for name in lock.images.keys() {
+            if !image_name_to_dependency.contains_key(name) {
+                if locked {
+                    miette::bail!(
+                        help = "run `tug lock` to update it",
+                        "{LOCK_FILE} locks unknown image `{name}`"
+                    );
+                }
+                logger.warn(format!("{LOCK_FILE} locks unknown image `{name}`"));
+            }
+        }
+    }
     let image_name_to_dependency = image_name_to_dependency
         .into_iter()
         .map(|(name, (reference, step, _))| (name, (reference, step)))
         .collect::<HashMap<_, _>>();
 
-    logger.log("Queueing networks");
+    logger.debug("Queueing networks");
     let mut network_name_to_dependency = HashMap::new();
     let mut counter = 1;
     for network in document.networks {
@@ -92,7 +161,7 @@ pub fn prepare(logger: &Logger, document: ParsedDocument, executor: &mut Executo
         .map(|(name, (reference, step, _))| (name, (reference, step)))
         .collect::<HashMap<_, _>>();
 
-    logger.log("Queueing volumes");
+    logger.debug("Queueing volumes");
     let mut volume_name_to_dependency = HashMap::new();
     let mut counter = 1;
     for volume in document.volumes {
@@ -117,48 +186,211 @@ pub fn prepare(logger: &Logger, document: ParsedDocument, executor: &mut Executo
         .map(|(name, (reference, step, _))| (name, (reference, step)))
         .collect::<HashMap<_, _>>();
 
-    logger.log("Queueing secrets");
+    logger.debug("Queueing pods");
+    let mut pod_name_to_dependency = HashMap::new();
+    let mut counter = 1;
+    for pod in document.pods {
+        let resolved = ResolvedPodRef(counter);
+        counter += 1;
+
+        let mut dependencies = Vec::new();
+        let networks = resolve_networks(pod.networks, &network_name_to_dependency, &mut dependencies)?;
+
+        let step_id = executor.new_step(
+            Action::Pod(PodAction {
+                name: pod.name.to_string(),
+                ports: pod.ports.into_iter().map(action_port).collect(),
+                networks,
+                resolved,
+            }),
+            BTreeSet::from_iter(dependencies),
+        );
+        if let Some((_, _, old_span)) =
+            pod_name_to_dependency.insert(pod.name.to_string(), (resolved, step_id, pod.name.span().clone()))
+        {
+            DuplicateName::from_spans(&old_span, pod.name.span())?
+        }
+    }
+    let pod_name_to_dependency = pod_name_to_dependency
+        .into_iter()
+        .map(|(name, (reference, step, _))| (name, (reference, step)))
+        .collect::<HashMap<_, _>>();
+
+    logger.debug("Queueing secrets");
     let mut counter = 1;
     let mut secret_to_dependency = HashMap::new();
-    for container in &document.containers {
-        for secret in &container.secrets {
-            if !secret_to_dependency.contains_key(secret.name.as_str()) {
-                let resolved = ResolvedSecretRef(counter);
-                counter += 1;
-                let step_id = executor.new_step(
-                    Action::Secret(SecretAction {
-                        resolved,
-                        name: secret.name.clone(),
-                    }),
-                    BTreeSet::new(),
-                );
-                secret_to_dependency.insert(secret.name.to_string(), (resolved, step_id));
-            }
+    let mut declared_secrets = HashMap::new();
+    for secret in document.secrets {
+        if let Some(existing) = declared_secrets.insert(secret.name.to_string(), secret.name.span().clone()) {
+            DuplicateName::from_spans(&existing, secret.name.span())?
+        }
+
+        let source = match (secret.from_file, secret.from_env, secret.from_command, secret.from_age) {
+            (Some(path), None, None, None) => SecretSource::File(path),
+            (None, Some(variable), None, None) => SecretSource::Env(variable),
+            (None, None, Some(command), None) => SecretSource::Command(required_command(command)?),
+            (None, None, None, Some(path)) => SecretSource::Age(path),
+            _ => Err(BadSecretSource {
+                content: read_source(secret.name.span())?,
+                here: secret.name.span().source_span(),
+                help: "use exactly one of `from-file`, `from-env`, `from-command` or `from-age`",
+            })?,
+        };
+
+        let resolved = ResolvedSecretRef(counter);
+        counter += 1;
+        let step_id = executor.new_step(
+            Action::Secret(SecretAction {
+                resolved,
+                name: secret.name.clone(),
+                source: Some(source),
+            }),
+            BTreeSet::new(),
+        );
+        secret_to_dependency.insert(secret.name.to_string(), (resolved, step_id));
+    }
+    let referenced_secrets = document
+        .containers
+        .iter()
+        .flat_map(|container| &container.secrets)
+        .chain(document.jobs.iter().flat_map(|job| &job.secrets))
+        .chain(document.schedules.iter().flat_map(|schedule| &schedule.secrets));
+    for secret in referenced_secrets {
+        if !secret_to_dependency.contains_key(secret.name.as_str()) {
+            let resolved = ResolvedSecretRef(counter);
+            counter += 1;
+            let step_id = executor.new_step(
+                Action::Secret(SecretAction {
+                    resolved,
+                    name: secret.name.clone(),
+                    source: None,
+                }),
+                BTreeSet::new(),
+            );
+            secret_to_dependency.insert(secret.name.to_string(), (resolved, step_id));
         }
     }
 
-    logger.log("Queueing containers");
+    logger.debug("Queueing jobs");
     let mut existing_names = HashMap::new();
+    let mut name_to_steps = HashMap::<String, Vec<usize>>::new();
+    let mut afters = Vec::new();
+    for job in document.jobs {
+        if let Some(existing) = existing_names.insert(job.name.to_string(), job.name.span().clone()) {
+            DuplicateName::from_spans(&existing, job.name.span())?
+        }
+
+        check_injects(&job.injects)?;
+
+        let (image_reference, image_step) = match image_name_to_dependency.get(job.image.as_str()) {
+            Some(v) => v,
+            None => return UnknownThing::build(job.image, "image"),
+        };
+
+        let mut dependencies = vec![*image_step];
+        let networks = resolve_networks(job.networks, &network_name_to_dependency, &mut dependencies)?;
+        let (volumes, binds) = resolve_mounts(job.mounts, &volume_name_to_dependency, &mut dependencies)?;
+        let secrets = resolve_secrets(job.secrets, &secret_to_dependency, &mut dependencies)?;
+
+        let step_id = executor.new_step(
+            Action::Job(JobAction {
+                name: job.name.to_string(),
+                command: split_command(job.command)?,
+                image: *image_reference,
+                env: job.env.into_iter().map(|env| (env.name, env.value)).collect(),
+                injects: job.injects,
+                networks,
+                volumes,
+                secrets,
+                binds,
+            }),
+            BTreeSet::from_iter(dependencies),
+        );
+        name_to_steps.insert(job.name.to_string(), vec![step_id]);
+        afters.push((job.name.to_string(), job.after));
+    }
+
+    logger.debug("Queueing schedules");
+    let mut schedule_names = HashMap::new();
+    for schedule in document.schedules {
+        if let Some(existing) = schedule_names.insert(schedule.name.to_string(), schedule.name.span().clone()) {
+            DuplicateName::from_spans(&existing, schedule.name.span())?
+        }
+
+        let Some(on_calendar) = cron::to_on_calendar(&schedule.cron) else {
+            Err(MalformedCron {
+                content: read_source(schedule.cron.span())?,
+                here: schedule.cron.span().source_span(),
+                help: "cron expressions look like \"0 3 * * *\" (minute, hour, day of month, month, day of week)",
+            })?
+        };
+
+        check_injects(&schedule.injects)?;
+
+        let (image_reference, image_step) = match image_name_to_dependency.get(schedule.image.as_str()) {
+            Some(v) => v,
+            None => return UnknownThing::build(schedule.image, "image"),
+        };
+
+        let mut dependencies = vec![*image_step];
+        let networks = resolve_networks(schedule.networks, &network_name_to_dependency, &mut dependencies)?;
+        let (volumes, binds) = resolve_mounts(schedule.mounts, &volume_name_to_dependency, &mut dependencies)?;
+        let secrets = resolve_secrets(schedule.secrets, &secret_to_dependency, &mut dependencies)?;
+
+        executor.new_step(
+            Action::Schedule(ScheduleAction {
+                job: JobAction {
+                    name: schedule.name.to_string(),
+                    command: split_command(schedule.command)?,
+                    image: *image_reference,
+                    env: schedule.env.into_iter().map(|env| (env.name, env.value)).collect(),
+                    injects: schedule.injects,
+                    networks,
+                    volumes,
+                    secrets,
+                    binds,
+                },
+                on_calendar,
+            }),
+            BTreeSet::from_iter(dependencies),
+        );
+    }
+
+    logger.trace("Checking hooks");
+    let mut post_create = HashMap::<String, (Spanned<String, ParseSpan>, Vec<Vec<String>>)>::new();
+    for hook in document.hooks {
+        match (*hook.kind, hook.command, hook.container, hook.exec) {
+            (ParsedHookKind::PreSync, Some(command), None, None) => executor.pre_sync.push(required_command(command)?),
+            (ParsedHookKind::PostSync, Some(command), None, None) => executor.post_sync.push(required_command(command)?),
+            (ParsedHookKind::PostCreate, None, Some(container), Some(exec)) => {
+                let command = required_command(exec)?;
+                post_create
+                    .entry(container.to_string())
+                    .or_insert_with(|| (container, Vec::new()))
+                    .1
+                    .push(command);
+            }
+            (ParsedHookKind::PreSync | ParsedHookKind::PostSync, ..) => Err(BadHook {
+                content: read_source(hook.kind.span())?,
+                here: hook.kind.span().source_span(),
+                help: "pre-sync and post-sync hooks only take a `command`, which runs locally",
+            })?,
+            (ParsedHookKind::PostCreate, ..) => Err(BadHook {
+                content: read_source(hook.kind.span())?,
+                here: hook.kind.span().source_span(),
+                help: "post-create hooks only take a `container` and the command to `exec` in it",
+            })?,
+        }
+    }
+
+    logger.debug("Queueing containers");
     for container in document.containers {
         if let Some(existing) = existing_names.insert(container.name.to_string(), container.name.span().clone()) {
             DuplicateName::from_spans(&existing, container.name.span())?
         }
 
         logger.trace("Checking injects");
-
-        {
-            let mut map = HashMap::with_capacity(container.injects.len());
-
-            for inject in container.injects.iter() {
-                if let Some(other) = map.insert(inject.at.as_os_str(), inject.at.span().clone()) {
-                    Err(DuplicateInjectPath {
-                        content: NamedSource::new(other.file.to_string_lossy(), std::fs::read_to_string(&other.file).d()?),
-                        first: other.source_span(),
-                        second: inject.at.span().source_span(),
-                    })?
-                }
-            }
-        }
+        check_injects(&container.injects)?;
 
         let (image_reference, image_step) = match image_name_to_dependency.get(container.image.as_str()) {
             Some(v) => v,
@@ -167,100 +399,312 @@ pub fn prepare(logger: &Logger, document: ParsedDocument, executor: &mut Executo
 
         let mut dependencies = vec![*image_step];
 
-        let mut networks = Vec::new();
-        for network in container.networks {
-            let (reference, step) = match network_name_to_dependency.get(network.name.as_str()) {
-                Some(v) => v,
-                None => return UnknownThing::build(network.name, "network"),
-            };
-            networks.push(ContainerActionNetwork {
-                resolved: *reference,
-                aliases: network.aliases,
-            });
-            dependencies.push(*step);
+        let replicas = replica_count(&container.replicas);
+        if let Some(spanned) = &container.replicas {
+            if replicas == 0 {
+                Err(NoReplicas {
+                    content: read_source(spanned.span())?,
+                    here: spanned.span().source_span(),
+                    help: "leave the container out to not run it, replicas start at 1",
+                })?
+            }
+            if container.pod.is_some() {
+                Err(ReplicatedPodMember {
+                    content: read_source(spanned.span())?,
+                    here: spanned.span().source_span(),
+                    help: "pod members share a network namespace, so replicas would fight over ports. drop one of them",
+                })?
+            }
+            if replicas > 1 && !container.ports.is_empty() {
+                Err(ReplicatedHostPorts {
+                    content: read_source(spanned.span())?,
+                    here: spanned.span().source_span(),
+                    help: "only one replica can bind a host port, so put a pod or a proxy in front instead",
+                })?
+            }
         }
 
-        let mut volumes = Vec::new();
-        let mut binds = Vec::new();
-        for mount in container.mounts {
-            match mount {
-                ParsedContainerMount::Volume(volume) => {
-                    let (reference, step) = match volume_name_to_dependency.get(volume.name.as_str()) {
-                        Some(v) => v,
-                        None => return UnknownThing::build(volume.name, "volume"),
-                    };
-                    volumes.push(ContainerActionVolumeMount {
-                        name_ref: *reference,
-                        destination: volume.destination,
-                    });
-                    dependencies.push(*step);
+        let pod = match container.pod {
+            Some(pod) => {
+                if !container.ports.is_empty() || !container.networks.is_empty() {
+                    Err(PodMemberNetworking {
+                        content: read_source(pod.span())?,
+                        here: pod.span().source_span(),
+                        help: "move the container's ports and networks to the pod",
+                    })?
                 }
-                ParsedContainerMount::Bind(bind) => binds.push(ContainerActionBindMount {
-                    source: bind.source,
-                    destination: bind.destination,
-                }),
+                let (reference, step) = match pod_name_to_dependency.get(pod.as_str()) {
+                    Some(v) => v,
+                    None => return UnknownThing::build(pod, "pod"),
+                };
+                dependencies.push(*step);
+                Some(*reference)
             }
+            None => None,
+        };
+
+        let networks = resolve_networks(container.networks, &network_name_to_dependency, &mut dependencies)?;
+        let (volumes, binds) = resolve_mounts(container.mounts, &volume_name_to_dependency, &mut dependencies)?;
+        let secrets = resolve_secrets(container.secrets, &secret_to_dependency, &mut dependencies)?;
+
+        let action = ContainerAction {
+            name: container.name.to_string(),
+            replica: 0,
+            command: split_command(container.command)?,
+            image: *image_reference,
+            pod,
+            update_strategy: container.update_strategy,
+            ports: container.ports.into_iter().map(action_port).collect(),
+            injects: container.injects,
+            networks,
+            volumes,
+            secrets,
+            binds,
+            post_create: post_create
+                .remove(container.name.as_str())
+                .map(|(_, commands)| commands)
+                .unwrap_or_default(),
+        };
+
+        // each replica waits for the previous one, so recreation rolls through them
+        // one at a time
+        let mut previous = None;
+        let mut steps = Vec::new();
+        for replica in 0..replicas {
+            let mut dependencies = BTreeSet::from_iter(dependencies.iter().copied());
+            dependencies.extend(previous);
+            let step_id = executor.new_step(
+                Action::Container(ContainerAction {
+                    replica,
+                    ..action.clone()
+                }),
+                dependencies,
+            );
+            previous = Some(step_id);
+            steps.push(step_id);
         }
+        name_to_steps.insert(container.name.to_string(), steps);
+        afters.push((container.name.to_string(), container.after));
+    }
 
-        let mut secrets = Vec::new();
-        for secret in container.secrets {
-            let (reference, step) = match secret_to_dependency.get(secret.name.as_str()) {
-                Some(v) => v,
-                None => return UnknownThing::build(secret.name, "secret"),
+    if let Some((_, (container, _))) = post_create.into_iter().next() {
+        return UnknownThing::build(container, "container");
+    }
+
+    logger.trace("Resolving after");
+    let mut graph = HashMap::new();
+    for (name, after) in afters {
+        for other in &after {
+            let Some(other_steps) = name_to_steps.get(other.as_str()) else {
+                return UnknownThing::build(other.clone(), "container or job");
             };
-            secrets.push(ContainerActionSecret {
-                name_ref: *reference,
-                target: secret.target.unwrap_or_else(|| secret.name.to_string()),
-            });
-            dependencies.push(*step);
+            for step in &name_to_steps[&name] {
+                for other_step in other_steps {
+                    executor.add_dependency(*step, *other_step);
+                }
+            }
         }
+        graph.insert(name, after);
+    }
+    check_cycles(&graph)?;
 
-        let command = if let Some(command) = container.command {
-            match shlex::split(&command) {
-                Some(command) => Some(command),
-                None => Err(MalformedCommand {
-                    content: read_source(command.span())?,
-                    here: command.span().source_span(),
-                })?,
-            }
-        } else {
-            None
+    Ok(())
+}
+
+fn check_injects(injects: &[ParsedContainerInject]) -> miette::Result<()> {
+    let mut map = HashMap::with_capacity(injects.len());
+
+    for inject in injects {
+        if let Some(other) = map.insert(inject.at.as_os_str(), inject.at.span().clone()) {
+            Err(DuplicateInjectPath {
+                content: NamedSource::new(other.file.to_string_lossy(), std::fs::read_to_string(&other.file).d()?),
+                first: other.source_span(),
+                second: inject.at.span().source_span(),
+            })?
+        }
+    }
+
+    Ok(())
+}
+
+fn resolve_networks(
+    networks: Vec<ParsedContainerNetwork>,
+    network_name_to_dependency: &HashMap<String, (ResolvedNetworkRef, usize)>,
+    dependencies: &mut Vec<usize>,
+) -> miette::Result<Vec<ContainerActionNetwork>> {
+    let mut resolved = Vec::new();
+    for network in networks {
+        let (reference, step) = match network_name_to_dependency.get(network.name.as_str()) {
+            Some(v) => v,
+            None => return UnknownThing::build(network.name, "network"),
         };
+        resolved.push(ContainerActionNetwork {
+            resolved: *reference,
+            aliases: network.aliases,
+        });
+        dependencies.push(*step);
+    }
 
-        executor.new_step(
-            Action::Container(ContainerAction {
-                name: container.name.to_string(),
-                command,
-                image: *image_reference,
-                ports: container
-                    .ports
-                    .into_iter()
-                    .map(|port| match port {
-                        ParsedContainerPort::Shorthand(port) => ContainerActionPort {
-                            container: port,
-                            host: port,
-                            protocol: ParsedProtocol::Tcp,
-                        },
-                        ParsedContainerPort::Explicit(ParsedExplicitContainerPort {
-                            container,
-                            host,
-                            protocol,
-                        }) => ContainerActionPort {
-                            container,
-                            host,
-                            protocol,
-                        },
-                    })
-                    .collect(),
-                injects: container.injects,
-                networks,
-                volumes,
-                secrets,
-                binds,
+    Ok(resolved)
+}
+
+fn resolve_mounts(
+    mounts: Vec<ParsedContainerMount>,
+    volume_name_to_dependency: &HashMap<String, (ResolvedVolumeRef, usize)>,
+    dependencies: &mut Vec<usize>,
+) -> miette::Result<(Vec<ContainerActionVolumeMount>, Vec<ContainerActionBindMount>)> {
+    let mut volumes = Vec::new();
+    let mut binds = Vec::new();
+    for mount in mounts {
+        match mount {
+            ParsedContainerMount::Volume(volume) => {
+                let (reference, step) = match volume_name_to_dependency.get(volume.name.as_str()) {
+                    Some(v) => v,
+                    None => return UnknownThing::build(volume.name, "volume"),
+                };
+                volumes.push(ContainerActionVolumeMount {
+                    name_ref: *reference,
+                    destination: volume.destination,
+                });
+                dependencies.push(*step);
+            }
+            ParsedContainerMount::Bind(bind) => binds.push(ContainerActionBindMount {
+                source: bind.source,
+                destination: bind.destination,
             }),
-            BTreeSet::from_iter(dependencies),
-        );
+        }
+    }
+
+    Ok((volumes, binds))
+}
+
+fn resolve_secrets(
+    secrets: Vec<ParsedContainerSecret>,
+    secret_to_dependency: &HashMap<String, (ResolvedSecretRef, usize)>,
+    dependencies: &mut Vec<usize>,
+) -> miette::Result<Vec<ContainerActionSecret>> {
+    let mut resolved = Vec::new();
+    for secret in secrets {
+        let (reference, step) = match secret_to_dependency.get(secret.name.as_str()) {
+            Some(v) => v,
+            None => return UnknownThing::build(secret.name, "secret"),
+        };
+        let kind = match secret.kind {
+            ParsedSecretType::Env => {
+                if secret.mode.is_some() || secret.uid.is_some() || secret.gid.is_some() {
+                    Err(EnvSecretMountOptions {
+                        content: read_source(secret.name.span())?,
+                        here: secret.name.span().source_span(),
+                        help: "mode, uid and gid only apply to secrets with type=\"mount\"",
+                    })?
+                }
+                ContainerActionSecretKind::Env
+            }
+            ParsedSecretType::Mount => {
+                let mode = match secret.mode {
+                    Some(mode) => match u32::from_str_radix(mode.trim_start_matches("0o"), 8) {
+                        Ok(parsed) if parsed <= 0o777 => Some(parsed),
+                        _ => Err(MalformedSecretMode {
+                            content: read_source(mode.span())?,
+                            here: mode.span().source_span(),
+                            help: "modes are octal permission bits, like \"0400\"",
+                        })?,
+                    },
+                    None => None,
+                };
+                ContainerActionSecretKind::Mount {
+                    mode,
+                    uid: secret.uid,
+                    gid: secret.gid,
+                }
+            }
+        };
+        resolved.push(ContainerActionSecret {
+            name_ref: *reference,
+            target: secret.target.unwrap_or_else(|| secret.name.to_string()),
+            kind,
+        });
+        dependencies.push(*step);
+    }
+
+    Ok(resolved)
+}
+
+fn split_command(command: Option<Spanned<String, ParseSpan>>) -> miette::Result<Option<Vec<String>>> {
+    let Some(command) = command else {
+        return Ok(None);
+    };
+    match shlex::split(&command) {
+        Some(split) => Ok(Some(split)),
+        None => Err(MalformedCommand {
+            content: read_source(command.span())?,
+            here: command.span().source_span(),
+        })?,
+    }
+}
+
+fn required_command(command: Spanned<String, ParseSpan>) -> miette::Result<Vec<String>> {
+    match shlex::split(&command) {
+        Some(split) if !split.is_empty() => Ok(split),
+        _ => Err(MalformedCommand {
+            content: read_source(command.span())?,
+            here: command.span().source_span(),
+        })?,
+    }
+}
+
+// `after` is the only way to make steps wait on each other across resources, so
+// it's the only place a cycle can sneak in and stall the executor
+fn check_cycles(graph: &HashMap<String, Vec<Spanned<String, ParseSpan>>>) -> miette::Result<()> {
+    fn visit<'a>(
+        name: &'a str,
+        graph: &'a HashMap<String, Vec<Spanned<String, ParseSpan>>>,
+        finished: &mut HashMap<&'a str, bool>,
+    ) -> miette::Result<()> {
+        finished.insert(name, false);
+        for other in &graph[name] {
+            match finished.get(other.as_str()) {
+                Some(true) => {}
+                Some(false) => Err(DependencyCycle {
+                    content: read_source(other.span())?,
+                    here: other.span().source_span(),
+                })?,
+                None => visit(other, graph, finished)?,
+            }
+        }
+        finished.insert(name, true);
+        Ok(())
+    }
+
+    let mut finished = HashMap::new();
+    for name in graph.keys() {
+        if !finished.contains_key(name.as_str()) {
+            visit(name, graph, &mut finished)?;
+        }
     }
 
     Ok(())
 }
+
+fn replica_count(replicas: &Option<Spanned<u32, ParseSpan>>) -> u32 {
+    replicas.as_ref().map_or(1, |replicas| **replicas)
+}
+
+fn action_port(port: ParsedContainerPort) -> ContainerActionPort {
+    match port {
+        ParsedContainerPort::Shorthand(port) => ContainerActionPort {
+            container: port,
+            host: port,
+            protocol: ParsedProtocol::Tcp,
+        },
+        ParsedContainerPort::Explicit(ParsedExplicitContainerPort {
+            container,
+            host,
+            protocol,
+        }) => ContainerActionPort {
+            container,
+            host,
+            protocol,
+        },
+    }
+}
@@ -0,0 +1,513 @@
+// finds credentials for image registries, the same places podman would look:
+// tug.toml first, then auth.json, with credential helpers for either
+
+use std::{
+    collections::BTreeMap,
+    fmt, io,
+    path::{Path, PathBuf},
+    process::Stdio,
+};
+
+use base64::{prelude::BASE64_STANDARD, Engine};
+use hyper::{
+    client::HttpConnector,
+    header::{ACCEPT, AUTHORIZATION, CONTENT_TYPE, WWW_AUTHENTICATE},
+    Body, Client, Method, Request, Response, StatusCode,
+};
+use hyper_rustls::HttpsConnector;
+use miette::Context;
+use podman_api::opts::RegistryAuth;
+use serde::Deserialize;
+use tokio::io::AsyncWriteExt;
+
+use crate::utils::IntoDiagnosticShorthand;
+
+#[derive(Deserialize, Clone)]
+pub struct Registry {
+    pub username: Option<String>,
+    pub password: Option<String>,
+    // runs `docker-credential-<helper> get`, like podman's credHelpers
+    pub credential_helper: Option<String>,
+}
+
+// the password stays out of debug output
+impl fmt::Debug for Registry {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Registry")
+            .field("username", &self.username)
+            .field("password", &self.password.as_ref().map(|_| "<redacted>"))
+            .field("credential_helper", &self.credential_helper)
+            .finish()
+    }
+}
+
+#[derive(Clone, Debug)]
+pub struct Credentials {
+    pub registries: BTreeMap<String, Registry>,
+    pub auth_file: Option<PathBuf>,
+}
+
+#[derive(Deserialize, Default)]
+#[serde(rename_all = "camelCase")]
+struct AuthFile {
+    #[serde(default)]
+    auths: BTreeMap<String, AuthEntry>,
+    #[serde(default)]
+    cred_helpers: BTreeMap<String, String>,
+}
+
+#[derive(Deserialize)]
+struct AuthEntry {
+    auth: Option<String>,
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "PascalCase")]
+struct HelperOutput {
+    username: String,
+    secret: String,
+}
+
+impl Credentials {
+    pub async fn for_reference(&self, reference: &str) -> miette::Result<Option<RegistryAuth>> {
+        let scopes = scopes(reference);
+        let host = scopes.last().expect("there's always a registry").clone();
+
+        for scope in &scopes {
+            if let Some(registry) = self.registries.get(scope) {
+                if let Some(helper) = &registry.credential_helper {
+                    return run_helper(helper, &host).await;
+                }
+                // an entry with nothing in it means pulling anonymously
+                if registry.username.is_none() && registry.password.is_none() {
+                    return Ok(None);
+                }
+                return Ok(Some(password_auth(
+                    registry.username.clone().unwrap_or_default(),
+                    registry.password.clone().unwrap_or_default(),
+                    &host,
+                )));
+            }
+        }
+
+        for path in self.auth_files() {
+            let auth_file = match read_auth_file(&path)? {
+                Some(auth_file) => auth_file,
+                None => continue,
+            };
+            let auths = auth_file
+                .auths
+                .iter()
+                .map(|(key, entry)| (normalize_key(key), entry))
+                .collect::<BTreeMap<_, _>>();
+            for scope in &scopes {
+                if let Some(encoded) = auths.get(scope.as_str()).and_then(|entry| entry.auth.as_ref()) {
+                    let decoded = BASE64_STANDARD
+                        .decode(encoded)
+                        .d()
+                        .and_then(|decoded| String::from_utf8(decoded).d())
+                        .wrap_err_with(|| format!("decoding credentials for {scope} in {}", path.display()))?;
+                    let (username, password) = decoded.split_once(':').unwrap_or((&decoded, ""));
+                    return Ok(Some(password_auth(username.into(), password.into(), &host)));
+                }
+            }
+            if let Some(helper) = auth_file.cred_helpers.get(&host) {
+                return run_helper(helper, &host).await;
+            }
+        }
+
+        Ok(None)
+    }
+
+    fn auth_files(&self) -> Vec<PathBuf> {
+        if let Some(auth_file) = &self.auth_file {
+            return vec![auth_file.clone()];
+        }
+        if let Some(auth_file) = std::env::var_os("REGISTRY_AUTH_FILE") {
+            return vec![auth_file.into()];
+        }
+
+        let mut paths = Vec::new();
+        if let Some(runtime_dir) = dirs::runtime_dir() {
+            paths.push(runtime_dir.join("containers/auth.json"));
+        }
+        if let Some(config_dir) = dirs::config_dir() {
+            paths.push(config_dir.join("containers/auth.json"));
+        }
+        if let Some(home_dir) = dirs::home_dir() {
+            paths.push(home_dir.join(".docker/config.json"));
+        }
+        paths
+    }
+}
+
+fn read_auth_file(path: &Path) -> miette::Result<Option<AuthFile>> {
+    match std::fs::read_to_string(path) {
+        Ok(content) => Ok(Some(
+            serde_json::from_str(&content)
+                .d()
+                .wrap_err_with(|| format!("reading {}", path.display()))?,
+        )),
+        Err(err) if err.kind() == io::ErrorKind::NotFound => Ok(None),
+        Err(err) => Err(err).d(),
+    }
+}
+
+fn password_auth(username: String, password: String, host: &str) -> RegistryAuth {
+    RegistryAuth::builder()
+        .username(username)
+        .password(password)
+        .server_address(host)
+        .build()
+}
+
+async fn run_helper(helper: &str, host: &str) -> miette::Result<Option<RegistryAuth>> {
+    let program = format!("docker-credential-{helper}");
+    let mut child = tokio::process::Command::new(&program)
+        .arg("get")
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn()
+        .d()
+        .wrap_err_with(|| format!("running `{program}`"))?;
+    child
+        .stdin
+        .take()
+        .expect("stdin should be piped")
+        .write_all(host.as_bytes())
+        .await
+        .d()?;
+    let output = child.wait_with_output().await.d()?;
+
+    if !output.status.success() {
+        // helpers report missing credentials on stdout, which isn't a failure
+        let message = String::from_utf8_lossy(if output.stderr.is_empty() {
+            &output.stdout
+        } else {
+            &output.stderr
+        })
+        .trim()
+        .to_string();
+        if message.contains("credentials not found") {
+            return Ok(None);
+        }
+        return Err(miette::miette!("`{program}` exited with {}: {message}", output.status));
+    }
+
+    let output: HelperOutput = serde_json::from_slice(&output.stdout)
+        .d()
+        .wrap_err_with(|| format!("reading output of `{program}`"))?;
+    // identity tokens come back under a magic username
+    Ok(Some(if output.username == "<token>" {
+        RegistryAuth::token(output.secret)
+    } else {
+        password_auth(output.username, output.secret, host)
+    }))
+}
+
+#[derive(Deserialize)]
+struct TokenResponse {
+    token: Option<String>,
+    access_token: Option<String>,
+}
+
+// manifest lists first, so the digest is the same whatever platform pulls it
+const MANIFEST_TYPES: &str = "application/vnd.oci.image.index.v1+json, \
+    application/vnd.docker.distribution.manifest.list.v2+json, \
+    application/vnd.oci.image.manifest.v1+json, \
+    application/vnd.docker.distribution.manifest.v2+json";
+
+// the `<repository>@<digest>` a reference points at in its registry right now,
+// asked from the registry itself so nothing gets pulled anywhere
+pub async fn manifest_digest(credentials: &Credentials, reference: &str) -> miette::Result<String> {
+    let repository = scopes(reference).swap_remove(0);
+    if let Some((_, digest)) = reference.split_once('@') {
+        return Ok(format!("{repository}@{digest}"));
+    }
+    let tag = reference[self::repository(reference).len()..]
+        .strip_prefix(':')
+        .unwrap_or("latest");
+    let (host, path) = repository.split_once('/').expect("there's always a registry");
+    let url = match host {
+        "docker.io" => format!("https://registry-1.docker.io/v2/{path}/manifests/{tag}"),
+        // local registries rarely bother with tls
+        _ if host == "localhost" || host.starts_with("localhost:") || host.starts_with("127.0.0.1") => {
+            format!("http://{host}/v2/{path}/manifests/{tag}")
+        }
+        _ => format!("https://{host}/v2/{path}/manifests/{tag}"),
+    };
+
+    let client = Client::builder().build(
+        hyper_rustls::HttpsConnectorBuilder::new()
+            .with_native_roots()
+            .https_or_http()
+            .enable_http1()
+            .build(),
+    );
+    let auth = credentials.for_reference(reference).await?;
+    let mut response = head_manifest(&client, &url, None).await?;
+    if response.status() == StatusCode::UNAUTHORIZED {
+        let challenge = response
+            .headers()
+            .get(WWW_AUTHENTICATE)
+            .and_then(|challenge| challenge.to_str().ok())
+            .unwrap_or_default()
+            .to_string();
+        if let Some(authorization) = authorize(&client, &challenge, auth.as_ref()).await? {
+            response = head_manifest(&client, &url, Some(&authorization)).await?;
+        }
+    }
+
+    match response.status() {
+        status if status.is_success() => {}
+        StatusCode::UNAUTHORIZED | StatusCode::FORBIDDEN => miette::bail!(
+            help = if auth.is_some() {
+                "the registry turned down the credentials tug found, check them in tug.toml or auth.json"
+            } else {
+                "no credentials found for it, add them to `registries` in tug.toml or run `podman login`"
+            },
+            "couldn't authenticate to {host}"
+        ),
+        StatusCode::NOT_FOUND => miette::bail!("{host} has no `{path}:{tag}`"),
+        status => miette::bail!("{host} responded with {status}"),
+    }
+    let digest = response
+        .headers()
+        .get("docker-content-digest")
+        .and_then(|digest| digest.to_str().ok())
+        .ok_or_else(|| miette::miette!("{host} didn't say which digest `{path}:{tag}` has"))?;
+
+    Ok(format!("{repository}@{digest}"))
+}
+
+async fn head_manifest(
+    client: &Client<HttpsConnector<HttpConnector>>,
+    url: &str,
+    authorization: Option<&str>,
+) -> miette::Result<Response<Body>> {
+    let mut request = Request::builder()
+        .method(Method::HEAD)
+        .uri(url)
+        .header(ACCEPT, MANIFEST_TYPES);
+    if let Some(authorization) = authorization {
+        request = request.header(AUTHORIZATION, authorization);
+    }
+    client
+        .request(request.body(Body::empty()).d()?)
+        .await
+        .d()
+        .wrap_err_with(|| format!("requesting {url}"))
+}
+
+// answers a registry's `WWW-Authenticate` challenge with whatever credentials
+// there are, bearer tokens are fetched from the realm it names
+async fn authorize(
+    client: &Client<HttpsConnector<HttpConnector>>,
+    challenge: &str,
+    auth: Option<&RegistryAuth>,
+) -> miette::Result<Option<String>> {
+    let basic = match auth {
+        Some(RegistryAuth::Password { username, password, .. }) => {
+            Some(format!("Basic {}", BASE64_STANDARD.encode(format!("{username}:{password}"))))
+        }
+        _ => None,
+    };
+    let (scheme, params) = challenge.split_once(' ').unwrap_or((challenge, ""));
+    if scheme.eq_ignore_ascii_case("basic") {
+        return Ok(basic);
+    }
+    if !scheme.eq_ignore_ascii_case("bearer") {
+        return Ok(None);
+    }
+
+    let params = challenge_params(params);
+    let Some(realm) = params.get("realm") else {
+        return Ok(None);
+    };
+    let mut query = url::form_urlencoded::Serializer::new(String::new());
+    for key in ["service", "scope"] {
+        if let Some(value) = params.get(key) {
+            query.append_pair(key, value);
+        }
+    }
+    let request = match auth {
+        // identity tokens are refresh tokens, they only work with the oauth flow
+        Some(RegistryAuth::Token { identity_token }) => {
+            query
+                .append_pair("grant_type", "refresh_token")
+                .append_pair("refresh_token", identity_token)
+                .append_pair("client_id", "tug");
+            Request::builder()
+                .method(Method::POST)
+                .uri(realm)
+                .header(CONTENT_TYPE, "application/x-www-form-urlencoded")
+                .body(Body::from(query.finish()))
+        }
+        _ => {
+            let mut request = Request::builder().uri(format!("{realm}?{}", query.finish()));
+            if let Some(basic) = &basic {
+                request = request.header(AUTHORIZATION, basic);
+            }
+            request.body(Body::empty())
+        }
+    };
+
+    let response = client
+        .request(request.d()?)
+        .await
+        .d()
+        .wrap_err_with(|| format!("requesting a token from {realm}"))?;
+    let status = response.status();
+    let body = hyper::body::to_bytes(response.into_body()).await.d()?;
+    if !status.is_success() {
+        // the manifest request reports it properly
+        return Ok(None);
+    }
+    let token = serde_json::from_slice::<TokenResponse>(&body)
+        .d()
+        .wrap_err_with(|| format!("reading the token from {realm}"))?;
+
+    Ok(token.token.or(token.access_token).map(|token| format!("Bearer {token}")))
+}
+
+// `realm="https://auth.docker.io/token",service="registry.docker.io"`, where
+// quoted values can hold commas
+fn challenge_params(params: &str) -> BTreeMap<String, String> {
+    let mut parsed = BTreeMap::new();
+    let mut rest = params.trim();
+    while let Some((key, after)) = rest.split_once('=') {
+        let key = key.trim().trim_start_matches(',').trim().to_lowercase();
+        let (value, after) = match after.strip_prefix('"') {
+            Some(quoted) => quoted.split_once('"').unwrap_or((quoted, "")),
+            None => after.split_once(',').unwrap_or((after, "")),
+        };
+        parsed.insert(key, value.to_string());
+        rest = after.trim_start_matches(',').trim();
+    }
+    parsed
+}
+
+// `registry:5000/name:tag` and `name@sha256:...` both have a repository of
+// everything before the tag or digest
+pub fn repository(reference: &str) -> &str {
+    let reference = reference.split_once('@').map_or(reference, |(name, _)| name);
+    match reference.rsplit_once(':') {
+        Some((name, tag)) if !tag.contains('/') => name,
+        _ => reference,
+    }
+}
+
+// the registry host an image comes from, with docker hub filled in for short names
+pub fn registry(reference: &str) -> String {
+    scopes(reference).pop().expect("there's always a registry")
+}
+
+// every prefix credentials can be scoped to, from the whole repository down to
+// just the registry host
+fn scopes(reference: &str) -> Vec<String> {
+    let repository = repository(reference);
+    let repository = match repository.split_once('/') {
+        Some((host, _)) if host.contains(['.', ':']) || host == "localhost" => repository.to_string(),
+        Some(_) => format!("docker.io/{repository}"),
+        None => format!("docker.io/library/{repository}"),
+    };
+
+    let mut scopes = vec![repository.clone()];
+    let mut scope = repository.as_str();
+    while let Some((parent, _)) = scope.rsplit_once('/') {
+        scopes.push(parent.to_string());
+        scope = parent;
+    }
+    scopes
+}
+
+// docker writes keys like `https://index.docker.io/v1/`
+fn normalize_key(key: &str) -> &str {
+    let key = key
+        .trim_start_matches("https://")
+        .trim_start_matches("http://")
+        .trim_end_matches('/')
+        .trim_end_matches("/v1")
+        .trim_end_matches("/v2");
+    match key {
+        "index.docker.io" | "registry-1.docker.io" => "docker.io",
+        key => key,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn scopes_fill_in_docker_hub() {
+        assert_eq!(
+            scopes("redis:7"),
+            ["docker.io/library/redis", "docker.io/library", "docker.io"]
+        );
+        assert_eq!(
+            scopes("grafana/grafana"),
+            ["docker.io/grafana/grafana", "docker.io/grafana", "docker.io"]
+        );
+    }
+
+    #[test]
+    fn scopes_keep_registry_hosts() {
+        assert_eq!(
+            scopes("registry.example.com:5000/team/app@sha256:abc"),
+            [
+                "registry.example.com:5000/team/app",
+                "registry.example.com:5000/team",
+                "registry.example.com:5000"
+            ]
+        );
+        assert_eq!(scopes("localhost/app:dev"), ["localhost/app", "localhost"]);
+    }
+
+    #[test]
+    fn normalize_docker_keys() {
+        assert_eq!(normalize_key("https://index.docker.io/v1/"), "docker.io");
+        assert_eq!(normalize_key("registry-1.docker.io"), "docker.io");
+        assert_eq!(normalize_key("http://registry.example.com/v2"), "registry.example.com");
+        assert_eq!(normalize_key("ghcr.io/team"), "ghcr.io/team");
+    }
+
+    #[test]
+    fn challenge_params_with_commas() {
+        let params = challenge_params(
+            r#"realm="https://auth.docker.io/token",service="registry.docker.io",scope="repository:a/b:pull,push""#,
+        );
+        assert_eq!(params["realm"], "https://auth.docker.io/token");
+        assert_eq!(params["service"], "registry.docker.io");
+        assert_eq!(params["scope"], "repository:a/b:pull,push");
+    }
+
+    #[test]
+    fn registry_password_is_redacted() {
+        let registry = Registry {
+            username: Some("deploy".into()),
+            password: Some("hunter2".into()),
+            credential_helper: None,
+        };
+        let debug = format!("{registry:?}");
+        assert!(debug.contains("deploy"));
+        assert!(!debug.contains("hunter2"));
+    }
+
+    #[tokio::test]
+    async fn empty_entries_mean_no_auth() {
+        let credentials = Credentials {
+            registries: BTreeMap::from([(
+                "ghcr.io".to_string(),
+                Registry {
+                    username: None,
+                    password: None,
+                    credential_helper: None,
+                },
+            )]),
+            auth_file: Some("/nonexistent/auth.json".into()),
+        };
+        assert!(credentials.for_reference("ghcr.io/team/app").await.unwrap().is_none());
+    }
+}
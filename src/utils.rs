@@ -7,8 +7,9 @@ use std::{
 use futures_util::{ready, AsyncWrite};
 use hyper::{
     body::{Bytes, Sender},
-    Body,
+    Body, Client, Method, Request,
 };
+use hyperlocal::UnixConnector;
 use miette::IntoDiagnostic;
 use serde::Serialize;
 
@@ -18,6 +19,14 @@ pub enum XTug {
     Group,
     InjectFingerprint,
     SecretFingerprint,
+    SecretDigest,
+    PodFingerprint,
+    Replica,
+    Job,
+    JobFingerprint,
+    Schedule,
+    History,
+    Revision,
 }
 
 impl AsRef<str> for XTug {
@@ -27,6 +36,14 @@ impl AsRef<str> for XTug {
             XTug::Group => "X-Tug-Group",
             XTug::InjectFingerprint => "X-Tug-Inject-Fingerprint",
             XTug::SecretFingerprint => "X-Tug-Secret-Fingerprint",
+            XTug::SecretDigest => "X-Tug-Secret-Digest",
+            XTug::PodFingerprint => "X-Tug-Pod-Fingerprint",
+            XTug::Replica => "X-Tug-Replica",
+            XTug::Job => "X-Tug-Job",
+            XTug::JobFingerprint => "X-Tug-Job-Fingerprint",
+            XTug::Schedule => "X-Tug-Schedule",
+            XTug::History => "X-Tug-History",
+            XTug::Revision => "X-Tug-Revision",
         }
     }
 }
@@ -56,24 +73,79 @@ impl<T, E: std::error::Error + Send + Sync + 'static> IntoDiagnosticShorthand<T,
     }
 }
 
+// podman-api doesn't let us control request bodies everywhere (secret creation
+// json-encodes the payload, for one), so this talks to the service directly for
+// the few endpoints that need it
+#[derive(Clone)]
+pub struct RawService {
+    uri: String,
+}
+
+impl RawService {
+    pub fn new(uri: &str) -> RawService {
+        RawService { uri: uri.to_string() }
+    }
+
+    pub fn uri(&self) -> &str {
+        &self.uri
+    }
+
+    pub async fn request(&self, method: Method, endpoint: &str, body: Body) -> miette::Result<Bytes> {
+        let request = Request::builder().method(method);
+        let response = match self.uri.split_once("://") {
+            Some(("unix", path)) => {
+                let request = request.uri(hyperlocal::Uri::new(path, endpoint)).body(body).d()?;
+                Client::builder().build(UnixConnector).request(request).await.d()?
+            }
+            Some(("tcp" | "http", host)) => {
+                let request = request.uri(format!("http://{host}{endpoint}")).body(body).d()?;
+                Client::new().request(request).await.d()?
+            }
+            _ => miette::bail!("unsupported service uri `{}`", self.uri),
+        };
+
+        let status = response.status();
+        let bytes = hyper::body::to_bytes(response.into_body()).await.d()?;
+        if !status.is_success() {
+            let message = serde_json::from_slice::<serde_json::Value>(&bytes)
+                .ok()
+                .and_then(|value| value.get("message")?.as_str().map(str::to_string))
+                .unwrap_or_else(|| String::from_utf8_lossy(&bytes).into_owned());
+            miette::bail!("service responded with {status}: {message}");
+        }
+
+        Ok(bytes)
+    }
+}
+
 pub struct BodyWriter {
-    sender: Sender,
+    sender: Option<Sender>,
 }
 
 impl BodyWriter {
     pub fn new() -> (BodyWriter, Body) {
         let (sender, body) = Body::channel();
-        (BodyWriter { sender }, body)
+        (BodyWriter { sender: Some(sender) }, body)
+    }
+
+    // fails the request instead of ending the body like it was complete
+    pub fn abort(&mut self) {
+        if let Some(sender) = self.sender.take() {
+            sender.abort();
+        }
     }
 }
 
 impl AsyncWrite for BodyWriter {
     fn poll_write(self: Pin<&mut Self>, cx: &mut Context<'_>, buf: &[u8]) -> Poll<std::io::Result<usize>> {
-        let this = self.get_mut();
-        if ready!(this.sender.poll_ready(cx)).is_err() {
+        let sender = match &mut self.get_mut().sender {
+            Some(sender) => sender,
+            None => return Poll::Ready(Err(std::io::ErrorKind::BrokenPipe.into())),
+        };
+        if ready!(sender.poll_ready(cx)).is_err() {
             return Poll::Ready(Err(std::io::ErrorKind::BrokenPipe.into()));
         }
-        if this.sender.try_send_data(Bytes::from(buf.to_vec())).is_err() {
+        if sender.try_send_data(Bytes::from(buf.to_vec())).is_err() {
             return Poll::Ready(Err(std::io::ErrorKind::BrokenPipe.into()));
         }
         Poll::Ready(Ok(buf.len()))